@@ -0,0 +1,206 @@
+//! Ethernet backend abstraction
+//!
+//! `service_socket` below drives the per-socket TCP state machine and
+//! Modbus dispatch purely in terms of an [`EthernetBackend`], so it has no
+//! idea whether it's talking to a W5500's hardware socket offload or an
+//! ENC424J600's raw MAC/PHY interface. [`W5500Backend`] wraps the
+//! register-level helpers in `common` to implement it; a raw-MAC controller
+//! would instead run its own software TCP state machine underneath the same
+//! five methods.
+
+use core::marker::PhantomData;
+
+use defmt::{info, warn};
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::chip::Chip;
+use crate::common::{self, DeviceConfig, MbapHeader, SensorData};
+
+/// Connection state of one backend socket, normalized across controllers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SocketState {
+    Closed,
+    Init,
+    Listening,
+    Established,
+    CloseWait,
+    /// Any other backend-specific transitional state
+    Other,
+}
+
+/// A network controller exposing per-socket TCP operations to the Modbus
+/// server. Implementations own whatever bus their chip needs; the trait
+/// only speaks in socket indices, normalized state, and bytes.
+pub trait EthernetBackend {
+    /// Bring a socket already in [`SocketState::Init`] up to LISTEN.
+    async fn listen(&mut self, socket: u8, port: u16) -> Result<(), ()>;
+
+    /// Current connection state of `socket`.
+    async fn status(&mut self, socket: u8) -> Result<SocketState, ()>;
+
+    /// Receive into `buffer`, returning the number of bytes read (0 if none
+    /// are available yet).
+    async fn recv(&mut self, socket: u8, buffer: &mut [u8]) -> Result<u16, ()>;
+
+    /// Send `data`, returning the number of bytes written.
+    async fn send(&mut self, socket: u8, data: &[u8]) -> Result<u16, ()>;
+
+    /// Fully reset `socket` (close, reconfigure, re-open, re-listen) on `port`.
+    async fn reopen(&mut self, socket: u8, port: u16) -> Result<(), ()>;
+}
+
+/// [`EthernetBackend`] over a WIZnet [`Chip`]'s hardware TCP socket offload.
+pub struct W5500Backend<C: Chip, SPI: SpiDevice> {
+    spi: SPI,
+    _chip: PhantomData<C>,
+}
+
+impl<C: Chip, SPI: SpiDevice> W5500Backend<C, SPI> {
+    pub fn new(spi: SPI) -> Self {
+        W5500Backend { spi, _chip: PhantomData }
+    }
+}
+
+impl<C: Chip, SPI: SpiDevice> EthernetBackend for W5500Backend<C, SPI> {
+    async fn listen(&mut self, socket: u8, _port: u16) -> Result<(), ()> {
+        common::listen_socket::<C, SPI>(&mut self.spi, socket).await
+    }
+
+    async fn status(&mut self, socket: u8) -> Result<SocketState, ()> {
+        let raw = common::check_socket_status::<C, SPI>(&mut self.spi, socket).await?;
+        Ok(match raw {
+            0x00 => SocketState::Closed,
+            0x13 => SocketState::Init,
+            0x14 => SocketState::Listening,
+            0x17 => SocketState::Established,
+            0x1C => SocketState::CloseWait,
+            _ => SocketState::Other,
+        })
+    }
+
+    async fn recv(&mut self, socket: u8, buffer: &mut [u8]) -> Result<u16, ()> {
+        common::read_rx_data::<C, SPI>(&mut self.spi, socket, buffer).await
+    }
+
+    async fn send(&mut self, socket: u8, data: &[u8]) -> Result<u16, ()> {
+        common::write_tx_data::<C, SPI>(&mut self.spi, socket, data).await
+    }
+
+    async fn reopen(&mut self, socket: u8, port: u16) -> Result<(), ()> {
+        common::open_socket::<C, SPI>(&mut self.spi, socket, port).await
+    }
+}
+
+/// Service one backend socket for a single connection-manager tick
+///
+/// Drives the socket's TCP state machine (CLOSED/INIT/LISTEN/ESTABLISHED)
+/// and, when a client is connected, reads and dispatches every complete
+/// Modbus TCP request a `recv` turns up, independently of every other
+/// socket. Each call uses its own stack-local RX/TX scratch buffers, so
+/// pipelined requests on different sockets never share state, and each
+/// response's MBAP transaction ID is echoed straight from that socket's own
+/// request. `assembler` is this socket's own [`common::ModbusFrameAssembler`]
+/// - callers keep one per socket, alongside `device_config`, so a frame
+/// split across hardware-offload `recv` calls survives to the next tick.
+///
+/// Returns `true` if the socket is ESTABLISHED (a master is connected).
+pub async fn service_socket<B: EthernetBackend>(
+    backend: &mut B,
+    socket: u8,
+    port: u16,
+    sensor_data: &SensorData,
+    device_config: &mut DeviceConfig,
+    assembler: &mut common::ModbusFrameAssembler,
+) -> bool {
+    let status = match backend.status(socket).await {
+        Ok(s) => s,
+        Err(_) => {
+            warn!("Socket {}: failed to read status", socket);
+            return false;
+        }
+    };
+
+    match status {
+        SocketState::Closed => {
+            warn!("Socket {} CLOSED - reopening", socket);
+            assembler.reset();
+            if backend.reopen(socket, port).await.is_err() {
+                warn!("Socket {}: failed to reopen", socket);
+            }
+            return false;
+        }
+        SocketState::CloseWait => {
+            info!("Socket {} CLOSE_WAIT - reopening", socket);
+            assembler.reset();
+            if backend.reopen(socket, port).await.is_err() {
+                warn!("Socket {}: failed to reopen", socket);
+            }
+            return false;
+        }
+        SocketState::Init => {
+            info!("Socket {} INIT - sending LISTEN", socket);
+            assembler.reset();
+            if backend.listen(socket, port).await.is_err() {
+                warn!("Socket {}: failed to send LISTEN", socket);
+            }
+            return false;
+        }
+        SocketState::Listening => {
+            return false;
+        }
+        SocketState::Established => {
+            // Fall through to request handling below
+        }
+        SocketState::Other => {
+            return false;
+        }
+    }
+
+    let mut buffer = [0u8; 260]; // Max Modbus TCP frame, this socket's own scratch space
+    let bytes_read = match backend.recv(socket, &mut buffer).await {
+        Ok(n) => n,
+        Err(_) => {
+            info!("Socket {}: failed to read RX data", socket);
+            return true;
+        }
+    };
+
+    if bytes_read == 0 {
+        return true;
+    }
+
+    let mut chunk = &buffer[..bytes_read as usize];
+    while let Some(data) = assembler.push(chunk) {
+        chunk = &[];
+        if service_frame(backend, socket, data, sensor_data, device_config).await.is_err() {
+            info!("Socket {}: failed to parse MBAP header", socket);
+        }
+    }
+
+    true
+}
+
+/// Dispatch one already-reassembled MBAP+PDU frame and send its response.
+async fn service_frame<B: EthernetBackend>(
+    backend: &mut B,
+    socket: u8,
+    data: &[u8],
+    sensor_data: &SensorData,
+    device_config: &mut DeviceConfig,
+) -> Result<(), ()> {
+    let mbap = match MbapHeader::from_bytes(data) {
+        Ok(mbap) => mbap,
+        Err(_) => return Err(()),
+    };
+
+    let pdu = &data[7..];
+    let mut response = [0u8; 260]; // This socket's own scratch space
+
+    if let Some(len) = common::dispatch_modbus_request(&mbap, pdu, sensor_data, device_config, &mut response) {
+        if backend.send(socket, &response[..len]).await.is_err() {
+            info!("Socket {}: failed to send response", socket);
+        }
+    }
+
+    Ok(())
+}
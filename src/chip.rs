@@ -0,0 +1,179 @@
+//! Chip abstraction for WIZnet-family Ethernet controllers
+//!
+//! Every WIZnet chip (W5500, W5100S, W5200, W6100, ...) exposes broadly the
+//! same common/socket register layout, but differs in how an address is
+//! encoded onto the SPI bus (block-select bits, frame width, fixed vs.
+//! variable addressing). The `Chip` trait captures that seam so the rest of
+//! `common` can talk in terms of register offsets and socket indices without
+//! caring which part is actually wired up. This mirrors how embassy split
+//! chip-specific framing out of its own w5500 driver.
+//!
+//! Only [`W5500`] is implemented today; a W5100S/W5200/W6100 impl is a
+//! drop-in addition once its framing is known, with no changes required to
+//! `init_hardware` or the Modbus server logic in `common`.
+
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A WIZnet Ethernet controller.
+///
+/// Implementations are restricted to this crate (sealed) since the
+/// associated constants describe a specific chip's register map, not a
+/// general-purpose interface third parties should implement against.
+pub trait Chip: sealed::Sealed {
+    /// Bus-level address, pre-encoded with whatever block-select/control
+    /// bits this chip's SPI framing requires.
+    type Address: Copy;
+
+    /// Chip Version register value expected back from `COMMON_VERSION`.
+    const VERSION: u8;
+
+    // Common register offsets
+    const COMMON_VERSION: u16;
+    const COMMON_SHAR0: u16; // Source MAC address (6 bytes)
+    const COMMON_SIPR0: u16; // Source IP address (4 bytes)
+    const COMMON_SUBR0: u16; // Subnet mask (4 bytes)
+    const COMMON_GAR0: u16; // Gateway address (4 bytes)
+
+    // Per-socket register offsets (relative to a socket's register block)
+    const SOCKET_MODE: u16;
+    const SOCKET_COMMAND: u16;
+    const SOCKET_STATUS: u16;
+    const SOCKET_PORT0: u16;
+    const SOCKET_TX_WRITE_PTR: u16;
+    const SOCKET_RX_READ_PTR: u16;
+    const SOCKET_RX_RECEIVED_SIZE: u16;
+
+    /// Encode an address in the chip's common register block.
+    fn common_addr(offset: u16) -> Self::Address;
+
+    /// Encode an address in socket `n`'s register block.
+    fn socket_addr(socket: u8, offset: u16) -> Self::Address;
+
+    /// Encode an address for a read within socket `n`'s RX buffer.
+    fn rx_addr(socket: u8, offset: u16) -> Self::Address;
+
+    /// Encode an address for a write within socket `n`'s TX buffer.
+    fn tx_addr(socket: u8, offset: u16) -> Self::Address;
+
+    /// Read `buffer.len()` bytes starting at `addr`.
+    ///
+    /// `spi` is any [`SpiDevice`], which owns chip-select assertion for the
+    /// duration of the transaction - no manual CS toggling here.
+    async fn bus_read<SPI: SpiDevice>(spi: &mut SPI, addr: Self::Address, buffer: &mut [u8]) -> Result<(), ()>;
+
+    /// Write `data` starting at `addr`.
+    async fn bus_write<SPI: SpiDevice>(spi: &mut SPI, addr: Self::Address, data: &[u8]) -> Result<(), ()>;
+}
+
+/// WIZnet W5500: 3-byte SPI header (`[addr_hi, addr_lo, control]`), one
+/// fixed 4-register block per socket (register, TX buffer, RX buffer, plus
+/// the unused 4th slot), Variable Data Length mode only.
+pub struct W5500;
+
+impl sealed::Sealed for W5500 {}
+
+/// W5500 bus address: a 16-bit offset plus the control byte encoding the
+/// block-select bits, read/write phase, and operation mode.
+#[derive(Clone, Copy)]
+pub struct W5500Address {
+    pub offset: u16,
+    pub control: u8,
+}
+
+impl W5500 {
+    const CONTROL_PHASE_READ: u8 = 0x00;
+    const CONTROL_PHASE_WRITE: u8 = 0x04;
+
+    const BSB_COMMON_REG: u8 = 0x00;
+
+    /// Block-select byte for socket `n`'s register block.
+    fn bsb_socket_reg(socket: u8) -> u8 {
+        socket * 4 + 1
+    }
+
+    /// Block-select byte for socket `n`'s TX buffer.
+    fn bsb_socket_tx(socket: u8) -> u8 {
+        socket * 4 + 2
+    }
+
+    /// Block-select byte for socket `n`'s RX buffer.
+    fn bsb_socket_rx(socket: u8) -> u8 {
+        socket * 4 + 3
+    }
+}
+
+impl Chip for W5500 {
+    type Address = W5500Address;
+
+    const VERSION: u8 = 0x04;
+
+    const COMMON_VERSION: u16 = 0x0039;
+    const COMMON_SHAR0: u16 = 0x0009;
+    const COMMON_SIPR0: u16 = 0x000F;
+    const COMMON_SUBR0: u16 = 0x0005;
+    const COMMON_GAR0: u16 = 0x0001;
+
+    const SOCKET_MODE: u16 = 0x0000;
+    const SOCKET_COMMAND: u16 = 0x0001;
+    const SOCKET_STATUS: u16 = 0x0003;
+    const SOCKET_PORT0: u16 = 0x0004;
+    const SOCKET_TX_WRITE_PTR: u16 = 0x0024;
+    const SOCKET_RX_READ_PTR: u16 = 0x0028;
+    const SOCKET_RX_RECEIVED_SIZE: u16 = 0x0026;
+
+    fn common_addr(offset: u16) -> Self::Address {
+        W5500Address {
+            offset,
+            control: (Self::BSB_COMMON_REG << 3) | Self::CONTROL_PHASE_READ,
+        }
+    }
+
+    fn socket_addr(socket: u8, offset: u16) -> Self::Address {
+        W5500Address {
+            offset,
+            control: (Self::bsb_socket_reg(socket) << 3) | Self::CONTROL_PHASE_READ,
+        }
+    }
+
+    fn rx_addr(socket: u8, offset: u16) -> Self::Address {
+        W5500Address {
+            offset,
+            control: (Self::bsb_socket_rx(socket) << 3) | Self::CONTROL_PHASE_READ,
+        }
+    }
+
+    fn tx_addr(socket: u8, offset: u16) -> Self::Address {
+        W5500Address {
+            offset,
+            control: (Self::bsb_socket_tx(socket) << 3) | Self::CONTROL_PHASE_WRITE,
+        }
+    }
+
+    async fn bus_read<SPI: SpiDevice>(spi: &mut SPI, addr: Self::Address, buffer: &mut [u8]) -> Result<(), ()> {
+        let header = [
+            (addr.offset >> 8) as u8,
+            (addr.offset & 0xFF) as u8,
+            addr.control & !0x04, // force read phase
+        ];
+
+        spi.transaction(&mut [Operation::Write(&header), Operation::Read(buffer)])
+            .await
+            .map_err(|_| ())
+    }
+
+    async fn bus_write<SPI: SpiDevice>(spi: &mut SPI, addr: Self::Address, data: &[u8]) -> Result<(), ()> {
+        let header = [
+            (addr.offset >> 8) as u8,
+            (addr.offset & 0xFF) as u8,
+            addr.control | 0x04, // force write phase
+        ];
+
+        spi.transaction(&mut [Operation::Write(&header), Operation::Write(data)])
+            .await
+            .map_err(|_| ())
+    }
+}
@@ -1,11 +1,13 @@
 //! Common code shared between modbus_1 and modbus_2
 //!
 //! This module contains:
-//! - Hardware initialization (W5500, SHT3x, OLED)
+//! - Hardware initialization (W5500, I2C1 bus, OLED)
 //! - Modbus TCP server implementation
-//! - Sensor reading tasks
 //! - OLED display tasks
 //! - Modbus register map
+//!
+//! Environmental sensor support (SHT3x/HTU21D/Si7021/AM2320, auto-detected
+//! on the I2C1 bus this module brings up) lives in [`crate::sensor`].
 
 use defmt::{info, warn};
 use embassy_stm32::{
@@ -16,7 +18,11 @@ use embassy_stm32::{
     spi::{Config as SpiConfig, Spi},
     time::Hertz,
 };
-use embassy_time::Timer;
+use embassy_time::{Delay, Timer};
+use embedded_hal_async::spi::SpiDevice;
+use embedded_hal_bus::spi::ExclusiveDevice;
+
+use crate::chip::Chip;
 
 // OLED display imports
 use ssd1306::{prelude::*, mode::BufferedGraphicsMode, I2CDisplayInterface, Ssd1306};
@@ -29,7 +35,7 @@ use embedded_graphics::{
 use core::fmt::Write;
 use heapless::String;
 
-// Bind I2C interrupts for SHT3x sensor (I2C1)
+// Bind I2C interrupts for the environmental sensor bus (I2C1)
 bind_interrupts!(struct I2c1Irqs {
     I2C1_EV => EventInterruptHandler<peripherals::I2C1>;
     I2C1_ER => ErrorInterruptHandler<peripherals::I2C1>;
@@ -41,47 +47,124 @@ bind_interrupts!(struct I2c1Irqs {
 
 /// Modbus register addresses (1-based addressing as per spec)
 pub mod registers {
-    pub const TEMP_REGISTERS: u16 = 40001;      // 40001-40002 (f32)
-    pub const HUMIDITY_REGISTERS: u16 = 40003;  // 40003-40004 (f32)
-    pub const STATUS_REGISTER: u16 = 40005;     // 40005 (u16)
-    pub const UPTIME_REGISTERS: u16 = 40006;    // 40006-40007 (u32)
-    pub const RESERVED_START: u16 = 40008;      // 40008-40010 (u16)
-    pub const RESERVED_END: u16 = 40010;
+    // Input registers (FC04, read-only) - live measured values. Boards
+    // without a given channel (e.g. no BME680 fitted) just serve 0.0 for it.
+    pub const TEMP_INPUT_REGISTERS: u16 = 30001;     // 30001-30002 (f32)
+    pub const HUMIDITY_INPUT_REGISTERS: u16 = 30003; // 30003-30004 (f32)
+    pub const PRESSURE_INPUT_REGISTERS: u16 = 30005; // 30005-30006 (f32, hPa)
+    pub const GAS_INPUT_REGISTERS: u16 = 30007;      // 30007-30008 (f32, Ohms)
+
+    // Holding registers (FC03/FC06/FC16) - status, derived values, and the
+    // writable actuator/config block. Measured values live in the input
+    // registers above instead, per conventional Modbus register conventions.
+    pub const STATUS_REGISTER: u16 = 40001;     // 40001 (u16)
+    pub const UPTIME_REGISTERS: u16 = 40002;    // 40002-40003 (u32)
+    pub const RESERVED_START: u16 = 40004;      // 40004-40006 (u16)
+    pub const RESERVED_END: u16 = 40006;
+
+    // Writable command block - actuator/config registers, not sensor data.
+    pub const HEATER_ENABLE_REGISTER: u16 = 40100;        // 0 = off, 1 = on
+    pub const MEASUREMENT_INTERVAL_REGISTER: u16 = 40101; // seconds between sensor reads
+    pub const DISPLAY_MODE_REGISTER: u16 = 40102;         // OLED display mode select
+    pub const WORD_ORDER_REGISTER: u16 = 40103;           // see `WordOrder` - 0=AbCd, 1=CdAb, 2=BaDc, 3=DcBa
+}
+
+/// Word order used when splitting a 32-bit value (f32/u32) across two 16-bit
+/// Modbus registers.
+///
+/// Different SCADA masters and PLC float decoders disagree on which half
+/// comes first, and a mismatch produces wildly wrong readings that are hard
+/// to diagnose from the wire alone - so this is exposed as a writable
+/// holding register (`registers::WORD_ORDER_REGISTER`) rather than fixed at
+/// build time, letting an integrator match their master without recompiling.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum WordOrder {
+    /// Big-endian word order: high word first, each word big-endian (the
+    /// previous hardcoded behavior).
+    AbCd,
+    /// Word-swapped: low word first, each word big-endian.
+    CdAb,
+    /// Byte-swapped: high word first, each word little-endian.
+    BaDc,
+    /// Byte- and word-swapped: low word first, each word little-endian.
+    DcBa,
+}
+
+impl WordOrder {
+    pub(crate) fn from_register(value: u16) -> Option<Self> {
+        match value {
+            0 => Some(WordOrder::AbCd),
+            1 => Some(WordOrder::CdAb),
+            2 => Some(WordOrder::BaDc),
+            3 => Some(WordOrder::DcBa),
+            _ => None,
+        }
+    }
 }
 
 /// Device status codes
+///
+/// `SensorData.status` reports one of the low-value state codes below,
+/// OR'd with `HEATER_ON` when the detected sensor's on-chip heater is active
+/// (SHT3x only, see [`crate::sensor::Sht3x::set_heater`]) - e.g. `OK |
+/// HEATER_ON` rather than a separate register for heater state.
 pub mod status {
     pub const OK: u16 = 0;
     pub const SENSOR_ERROR: u16 = 1;
     pub const NETWORK_ERROR: u16 = 2;
+
+    /// The detected sensor's on-chip heater is on, on parts that have one.
+    pub const HEATER_ON: u16 = 0x8000;
 }
 
 // ============================================================================
 // Hardware Initialization
 // ============================================================================
 
+/// W5500 SPI bus as an [`embedded_hal_async::spi::SpiDevice`]
+///
+/// `ExclusiveDevice` owns the CS pin and asserts/deasserts it around each
+/// transaction itself, so callers never toggle CS by hand. It only supports
+/// one device at a time; sharing SPI1 with another peripheral (an SD card,
+/// external flash, ...) would mean swapping this for a mutex-backed
+/// `SpiDevice` (e.g. `embassy_embedded_hal::shared_bus`) without touching
+/// anything downstream, since all of `Chip`/`common` only ever talk in
+/// terms of `SpiDevice`.
+pub type W5500SpiDevice = ExclusiveDevice<
+    Spi<'static, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
+    Output<'static, peripherals::PB6>,
+    Delay,
+>;
+
 /// Initialize hardware peripherals for Modbus TCP node
 ///
-/// Returns (SPI, CS pin) so main loop can monitor socket status
+/// Returns the W5500's `SpiDevice` handle so the main loop can monitor
+/// socket status.
+///
+/// Generic over `C: Chip` so a different WIZnet part (W5100S, W5200, W6100,
+/// ...) can be dropped in by instantiating with a different chip type; the
+/// body below never touches chip-specific register encoding directly.
+///
+/// Expects `embassy_stm32::init` to have already run once in `main` - boards
+/// that resolve their identity from `crate::flash_store` need to read flash
+/// before bringing up the network, so the one-time `init()` call lives in
+/// the caller now rather than in here; this just steals the already-live
+/// `Peripherals`, the same way `rtu::init_rtu` and the sensor/OLED init
+/// helpers re-acquire peripherals after the first `init()`.
 ///
 /// # Arguments
 /// * `board_id` - Identifier string for logging ("Board 1" or "Board 2")
 /// * `ip_addr` - Static IP address [a, b, c, d]
 /// * `mac_addr` - MAC address [a, b, c, d, e, f]
-pub async fn init_hardware(
+pub async fn init_hardware<C: Chip>(
     board_id: &str,
     ip_addr: [u8; 4],
     mac_addr: [u8; 6],
-) -> (
-    Spi<'static, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    Output<'static, peripherals::PB6>,
-) {
+) -> W5500SpiDevice {
     info!("Initializing hardware for {}", board_id);
     info!("IP: {}.{}.{}.{}", ip_addr[0], ip_addr[1], ip_addr[2], ip_addr[3]);
 
-    // Initialize Embassy peripherals
-    let p = embassy_stm32::init(Default::default());
-    info!("Embassy peripherals initialized");
+    let p = unsafe { embassy_stm32::Peripherals::steal() };
 
     // ========================================================================
     // W5500 SPI Configuration
@@ -106,9 +189,13 @@ pub async fn init_hardware(
     info!("SPI1 initialized at 10 MHz");
 
     // Configure CS pin (PB6) - Active low, start HIGH (deselected)
-    let mut cs_pin = Output::new(p.PB6, Level::High, Speed::VeryHigh);
+    let cs_pin = Output::new(p.PB6, Level::High, Speed::VeryHigh);
     info!("CS pin configured: PB6 (initial: HIGH)");
 
+    // Wrap the bus + CS pin as an SpiDevice: it asserts/deasserts CS around
+    // each transaction, so nothing downstream touches the pin directly.
+    let mut spi_device = ExclusiveDevice::new(spi, cs_pin, Delay).expect("Failed to create W5500 SpiDevice");
+
     // Configure RST pin (PC7) - Active low, start HIGH (not in reset)
     let mut rst_pin = Output::new(p.PC7, Level::High, Speed::VeryHigh);
     info!("RST pin configured: PC7 (initial: HIGH)");
@@ -127,20 +214,20 @@ pub async fn init_hardware(
     // ========================================================================
     // Test W5500 Communication - Read Version Register
     // ========================================================================
-    info!("Reading W5500 version register at 0x0039 (expecting 0x04)...");
-    match w5500_read_register(&mut spi, &mut cs_pin, REG_VERSIONR).await {
+    info!("Reading chip version register (expecting 0x{:02X})...", C::VERSION);
+    match read_register::<C, _>(&mut spi_device, C::COMMON_VERSION).await {
         Ok(version) => {
-            if version == 0x04 {
-                info!("W5500 version: 0x{:02X} - CORRECT! SPI working! ✓", version);
+            if version == C::VERSION {
+                info!("Chip version: 0x{:02X} - CORRECT! SPI working! ✓", version);
             } else {
-                warn!("W5500 version: 0x{:02X} - UNEXPECTED (expected 0x04)", version);
-                warn!("This may indicate: wrong wiring, unpowered W5500, or SPI config issue");
-                panic!("W5500 initialization failed - wrong version");
+                warn!("Chip version: 0x{:02X} - UNEXPECTED (expected 0x{:02X})", version, C::VERSION);
+                warn!("This may indicate: wrong wiring, unpowered chip, or SPI config issue");
+                panic!("Ethernet chip initialization failed - wrong version");
             }
         }
         Err(_) => {
-            warn!("Failed to read W5500 version register - SPI communication error");
-            panic!("W5500 SPI communication failed");
+            warn!("Failed to read chip version register - SPI communication error");
+            panic!("Ethernet chip SPI communication failed");
         }
     }
 
@@ -152,201 +239,81 @@ pub async fn init_hardware(
     // Gateway address (10.10.10.1)
     let gateway = [10, 10, 10, 1];
     info!("Setting Gateway: {}.{}.{}.{}", gateway[0], gateway[1], gateway[2], gateway[3]);
-    w5500_write_register(&mut spi, &mut cs_pin, REG_GAR0, &gateway)
+    write_register::<C, _>(&mut spi_device, C::COMMON_GAR0, &gateway)
         .await
         .expect("Failed to write gateway address");
 
     // Subnet mask (255.255.255.0)
     let subnet = [255, 255, 255, 0];
     info!("Setting Subnet: {}.{}.{}.{}", subnet[0], subnet[1], subnet[2], subnet[3]);
-    w5500_write_register(&mut spi, &mut cs_pin, REG_SUBR0, &subnet)
+    write_register::<C, _>(&mut spi_device, C::COMMON_SUBR0, &subnet)
         .await
         .expect("Failed to write subnet mask");
 
     // MAC address
     info!("Setting MAC: {:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
           mac_addr[0], mac_addr[1], mac_addr[2], mac_addr[3], mac_addr[4], mac_addr[5]);
-    w5500_write_register(&mut spi, &mut cs_pin, REG_SHAR0, &mac_addr)
+    write_register::<C, _>(&mut spi_device, C::COMMON_SHAR0, &mac_addr)
         .await
         .expect("Failed to write MAC address");
 
     // IP address
     info!("Setting IP: {}.{}.{}.{}", ip_addr[0], ip_addr[1], ip_addr[2], ip_addr[3]);
-    w5500_write_register(&mut spi, &mut cs_pin, REG_SIPR0, &ip_addr)
+    write_register::<C, _>(&mut spi_device, C::COMMON_SIPR0, &ip_addr)
         .await
         .expect("Failed to write IP address");
 
     info!("W5500 network configuration complete!");
 
     // ========================================================================
-    // Open TCP Socket on Port 502 (Modbus TCP)
+    // Open TCP Sockets on Port 502 (Modbus TCP)
     // ========================================================================
-    info!("Opening TCP socket on port 502...");
-
-    // Step 0: Close socket first to ensure clean state
-    info!("Ensuring Socket 0 is closed");
-    w5500_write_socket_register(&mut spi, &mut cs_pin, REG_S0_CR, SOCK_CMD_CLOSE)
-        .await
-        .expect("Failed to send CLOSE command");
-    Timer::after_millis(10).await;
-
-    // Step 1: Set socket mode to TCP
-    info!("Setting Socket 0 mode to TCP");
-    w5500_write_socket_register(&mut spi, &mut cs_pin, REG_S0_MR, SOCK_MODE_TCP)
-        .await
-        .expect("Failed to set socket mode");
-
-    // Step 2: Set source port (502 in big-endian)
-    let port_bytes = [0x01, 0xF6]; // 502 = 0x01F6
-    info!("Setting Socket 0 port to 502");
-    w5500_write_socket_register_multi(&mut spi, &mut cs_pin, REG_S0_PORT0, &port_bytes)
-        .await
-        .expect("Failed to set socket port");
-
-    // Step 3: Send OPEN command
-    info!("Sending OPEN command to Socket 0");
-    w5500_write_socket_register(&mut spi, &mut cs_pin, REG_S0_CR, SOCK_CMD_OPEN)
-        .await
-        .expect("Failed to send OPEN command");
-
-    // Step 4: Wait for command to be processed
-    Timer::after_millis(10).await;
-
-    // Poll command register until it clears (max 100ms)
-    let mut cmd_cleared = false;
-    for _ in 0..10 {
-        let cmd = w5500_read_socket_register(&mut spi, &mut cs_pin, REG_S0_CR)
-            .await
-            .expect("Failed to read command register");
-        if cmd == 0x00 {
-            cmd_cleared = true;
-            break;
-        }
-        Timer::after_millis(10).await;
-    }
-
-    if !cmd_cleared {
-        warn!("OPEN command did not clear after 100ms");
-    }
-
-    let status = w5500_read_socket_register(&mut spi, &mut cs_pin, REG_S0_SR)
-        .await
-        .expect("Failed to read socket status after OPEN");
-
-    if status == SOCK_STATUS_INIT {
-        info!("Socket 0 opened successfully (status: 0x{:02X})", status);
-    } else {
-        panic!("Socket 0 unexpected status after OPEN: 0x{:02X} (expected 0x{:02X})",
-               status, SOCK_STATUS_INIT);
-    }
-
-    // Step 5: Send LISTEN command (TCP server mode)
-    info!("Sending LISTEN command to Socket 0");
-    w5500_write_socket_register(&mut spi, &mut cs_pin, REG_S0_CR, SOCK_CMD_LISTEN)
-        .await
-        .expect("Failed to send LISTEN command");
-
-    // Step 6: Wait for command to be processed and status to change
-    Timer::after_millis(10).await;
-
-    // Poll command register until it clears (max 100ms)
-    let mut cmd_cleared = false;
-    for _ in 0..10 {
-        let cmd = w5500_read_socket_register(&mut spi, &mut cs_pin, REG_S0_CR)
-            .await
-            .expect("Failed to read command register");
-        if cmd == 0x00 {
-            cmd_cleared = true;
-            break;
-        }
-        Timer::after_millis(10).await;
-    }
-
-    if !cmd_cleared {
-        warn!("LISTEN command did not clear after 100ms");
-    }
-
-    // Additional wait for status register to update
-    Timer::after_millis(50).await;
-
-    // Poll status register until it changes to LISTEN (max 200ms)
-    let mut status = 0x00;
-    let mut listen_achieved = false;
-    for i in 0..20 {
-        status = w5500_read_socket_register(&mut spi, &mut cs_pin, REG_S0_SR)
-            .await
-            .expect("Failed to read socket status");
-
-        info!("Poll {} - Socket status: 0x{:02X}", i, status);
-
-        if status == SOCK_STATUS_LISTEN {
-            listen_achieved = true;
-            break;
-        }
-        Timer::after_millis(10).await;
-    }
-
-    if listen_achieved {
-        info!("Socket 0 LISTENING on port 502 (status: 0x{:02X}) ✓", status);
-    } else {
-        warn!("Socket 0 did not reach LISTEN state after 200ms");
-        warn!("Final status: 0x{:02X} (expected 0x{:02X})", status, SOCK_STATUS_LISTEN);
-        // Don't panic - continue anyway and see if it works
-    }
+    // Bring up every hardware socket in LISTEN so multiple Modbus masters
+    // (e.g. an HMI and a historian) can each hold their own connection.
+    info!("Opening {} TCP sockets on port 502...", NUM_SOCKETS);
+    open_listening_sockets::<C, _>(&mut spi_device, 502, NUM_SOCKETS).await;
 
     info!("TCP server ready on port 502!");
     info!("Hardware initialization complete for {}", board_id);
 
-    // Return SPI and CS pin for socket monitoring
-    (spi, cs_pin)
+    // Return the SpiDevice handle for socket monitoring
+    spi_device
 }
 
-/// Check socket status and return current state
-pub async fn check_socket_status(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-) -> Result<u8, ()> {
-    w5500_read_socket_register(spi, cs, REG_S0_SR).await
-}
+/// Number of hardware sockets available on a WIZnet chip (W5500: 0-7)
+pub const NUM_SOCKETS: u8 = 8;
 
-/// Close socket (send CLOSE command)
-pub async fn close_socket(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-) -> Result<(), ()> {
-    w5500_write_socket_register(spi, cs, REG_S0_CR, SOCK_CMD_CLOSE).await?;
-    Timer::after_millis(10).await;
-    Ok(())
+/// Check socket status and return current state
+pub async fn check_socket_status<C: Chip, SPI: SpiDevice>(spi: &mut SPI, socket: u8) -> Result<u8, ()> {
+    read_socket_register::<C, SPI>(spi, socket, C::SOCKET_STATUS).await
 }
 
 /// Send LISTEN command to socket (assumes socket is in INIT state)
-pub async fn listen_socket(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-) -> Result<(), ()> {
-    w5500_write_socket_register(spi, cs, REG_S0_CR, SOCK_CMD_LISTEN).await?;
+pub async fn listen_socket<C: Chip, SPI: SpiDevice>(spi: &mut SPI, socket: u8) -> Result<(), ()> {
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_COMMAND, SOCK_CMD_LISTEN).await?;
     Timer::after_millis(10).await;
     Ok(())
 }
 
-/// Reopen socket (CLOSE -> OPEN -> LISTEN sequence)
-pub async fn reopen_socket(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-) -> Result<(), ()> {
+/// Open a socket in TCP server mode and bring it up to LISTEN on `port`
+///
+/// Runs the full CLOSE -> MODE -> PORT -> OPEN -> LISTEN sequence. Used both
+/// for initial bring-up of each socket and to reopen one after it drops back
+/// to CLOSED (e.g. after a client disconnects from a non-reusable socket).
+pub async fn open_socket<C: Chip, SPI: SpiDevice>(spi: &mut SPI, socket: u8, port: u16) -> Result<(), ()> {
     // Step 1: Ensure socket is closed and wait for CLOSED status
-    w5500_write_socket_register(spi, cs, REG_S0_CR, SOCK_CMD_CLOSE).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_COMMAND, SOCK_CMD_CLOSE).await?;
     Timer::after_millis(10).await;
 
-    // Wait for socket to reach CLOSED state
     let mut retries = 20;
     loop {
-        let status = w5500_read_socket_register(spi, cs, REG_S0_SR).await?;
-        if status == 0x00 {  // CLOSED
+        let status = read_socket_register::<C, SPI>(spi, socket, C::SOCKET_STATUS).await?;
+        if status == 0x00 {
+            // CLOSED
             break;
         }
         if retries == 0 {
-            warn!("Socket did not close properly");
+            warn!("Socket {} did not close properly", socket);
             return Err(());
         }
         retries -= 1;
@@ -354,26 +321,26 @@ pub async fn reopen_socket(
     }
 
     // Step 2: Set socket mode to TCP
-    w5500_write_socket_register(spi, cs, REG_S0_MR, SOCK_MODE_TCP).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_MODE, SOCK_MODE_TCP).await?;
 
-    // Step 3: Set source port (502 in big-endian)
-    let port_bytes = [0x01, 0xF6]; // 502 = 0x01F6
-    w5500_write_socket_register(spi, cs, REG_S0_PORT0, port_bytes[0]).await?;
-    w5500_write_socket_register(spi, cs, REG_S0_PORT0 + 1, port_bytes[1]).await?;
+    // Step 3: Set source port (big-endian)
+    let port_bytes = port.to_be_bytes();
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_PORT0, port_bytes[0]).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_PORT0 + 1, port_bytes[1]).await?;
 
     // Step 4: Send OPEN command
-    w5500_write_socket_register(spi, cs, REG_S0_CR, SOCK_CMD_OPEN).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_COMMAND, SOCK_CMD_OPEN).await?;
     Timer::after_millis(10).await;
 
     // Step 5: Wait for INIT status
     let mut retries = 20;
     loop {
-        let status = w5500_read_socket_register(spi, cs, REG_S0_SR).await?;
+        let status = read_socket_register::<C, SPI>(spi, socket, C::SOCKET_STATUS).await?;
         if status == SOCK_STATUS_INIT {
             break;
         }
         if retries == 0 {
-            warn!("Socket did not reach INIT state");
+            warn!("Socket {} did not reach INIT state", socket);
             return Err(());
         }
         retries -= 1;
@@ -381,19 +348,19 @@ pub async fn reopen_socket(
     }
 
     // Step 6: Send LISTEN command
-    w5500_write_socket_register(spi, cs, REG_S0_CR, SOCK_CMD_LISTEN).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_COMMAND, SOCK_CMD_LISTEN).await?;
     Timer::after_millis(10).await;
 
     // Step 7: Wait for LISTEN status
     let mut retries = 20;
     loop {
-        let status = w5500_read_socket_register(spi, cs, REG_S0_SR).await?;
+        let status = read_socket_register::<C, SPI>(spi, socket, C::SOCKET_STATUS).await?;
         if status == SOCK_STATUS_LISTEN {
-            info!("Socket reopened and listening on port 502");
+            info!("Socket {} listening on port {}", socket, port);
             return Ok(());
         }
         if retries == 0 {
-            warn!("Socket did not reach LISTEN state");
+            warn!("Socket {} did not reach LISTEN state", socket);
             return Err(());
         }
         retries -= 1;
@@ -401,48 +368,58 @@ pub async fn reopen_socket(
     }
 }
 
+/// Open and LISTEN every socket in `0..count` on `port`
+///
+/// This is the connection-manager bring-up step: with every socket in
+/// LISTEN, up to `count` Modbus masters (e.g. an HMI and a historian) can
+/// each hold their own established connection simultaneously.
+pub async fn open_listening_sockets<C: Chip, SPI: SpiDevice>(spi: &mut SPI, port: u16, count: u8) {
+    for socket in 0..count.min(NUM_SOCKETS) {
+        if open_socket::<C, SPI>(spi, socket, port).await.is_err() {
+            warn!("Failed to bring socket {} to LISTEN", socket);
+        }
+    }
+}
+
 /// Check how many bytes are available in RX buffer
-pub async fn check_rx_size(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-) -> Result<u16, ()> {
-    // REG_S0_RX_RSR0 is a 2-byte register
-    let high = w5500_read_socket_register(spi, cs, REG_S0_RX_RSR0).await?;
-    let low = w5500_read_socket_register(spi, cs, REG_S0_RX_RSR0 + 1).await?;
+pub async fn check_rx_size<C: Chip, SPI: SpiDevice>(spi: &mut SPI, socket: u8) -> Result<u16, ()> {
+    // C::SOCKET_RX_RECEIVED_SIZE is a 2-byte register
+    let high = read_socket_register::<C, SPI>(spi, socket, C::SOCKET_RX_RECEIVED_SIZE).await?;
+    let low = read_socket_register::<C, SPI>(spi, socket, C::SOCKET_RX_RECEIVED_SIZE + 1).await?;
     Ok(u16::from_be_bytes([high, low]))
 }
 
 /// Read data from RX buffer
 ///
 /// Returns the number of bytes actually read (up to buffer.len())
-pub async fn read_rx_data(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
+pub async fn read_rx_data<C: Chip, SPI: SpiDevice>(
+    spi: &mut SPI,
+    socket: u8,
     buffer: &mut [u8],
 ) -> Result<u16, ()> {
     // Step 1: Check how many bytes are available
-    let rx_size = check_rx_size(spi, cs).await?;
+    let rx_size = check_rx_size::<C, SPI>(spi, socket).await?;
     if rx_size == 0 {
         return Ok(0);
     }
 
     // Step 2: Read current RX read pointer (2 bytes, big-endian)
-    let ptr_high = w5500_read_socket_register(spi, cs, REG_S0_RX_RD0).await?;
-    let ptr_low = w5500_read_socket_register(spi, cs, REG_S0_RX_RD0 + 1).await?;
+    let ptr_high = read_socket_register::<C, SPI>(spi, socket, C::SOCKET_RX_READ_PTR).await?;
+    let ptr_low = read_socket_register::<C, SPI>(spi, socket, C::SOCKET_RX_READ_PTR + 1).await?;
     let rx_ptr = u16::from_be_bytes([ptr_high, ptr_low]);
 
     // Step 3: Read data from RX buffer (limited by buffer size)
     let bytes_to_read = rx_size.min(buffer.len() as u16);
-    w5500_read_rx_buffer(spi, cs, rx_ptr, &mut buffer[..bytes_to_read as usize]).await?;
+    read_rx_buffer::<C, SPI>(spi, socket, rx_ptr, &mut buffer[..bytes_to_read as usize]).await?;
 
     // Step 4: Update RX read pointer
     let new_ptr = rx_ptr.wrapping_add(bytes_to_read);
     let new_ptr_bytes = new_ptr.to_be_bytes();
-    w5500_write_socket_register(spi, cs, REG_S0_RX_RD0, new_ptr_bytes[0]).await?;
-    w5500_write_socket_register(spi, cs, REG_S0_RX_RD0 + 1, new_ptr_bytes[1]).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_RX_READ_PTR, new_ptr_bytes[0]).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_RX_READ_PTR + 1, new_ptr_bytes[1]).await?;
 
     // Step 5: Send RECV command to finalize read operation
-    w5500_write_socket_register(spi, cs, REG_S0_CR, SOCK_CMD_RECV).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_COMMAND, SOCK_CMD_RECV).await?;
 
     Ok(bytes_to_read)
 }
@@ -450,32 +427,28 @@ pub async fn read_rx_data(
 /// Write data to TX buffer and send
 ///
 /// Returns number of bytes written
-pub async fn write_tx_data(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-    data: &[u8],
-) -> Result<u16, ()> {
+pub async fn write_tx_data<C: Chip, SPI: SpiDevice>(spi: &mut SPI, socket: u8, data: &[u8]) -> Result<u16, ()> {
     if data.is_empty() {
         return Ok(0);
     }
 
     // Step 1: Read current TX write pointer (2 bytes, big-endian)
-    let ptr_high = w5500_read_socket_register(spi, cs, REG_S0_TX_WR0).await?;
-    let ptr_low = w5500_read_socket_register(spi, cs, REG_S0_TX_WR0 + 1).await?;
+    let ptr_high = read_socket_register::<C, SPI>(spi, socket, C::SOCKET_TX_WRITE_PTR).await?;
+    let ptr_low = read_socket_register::<C, SPI>(spi, socket, C::SOCKET_TX_WRITE_PTR + 1).await?;
     let tx_ptr = u16::from_be_bytes([ptr_high, ptr_low]);
 
     // Step 2: Write data to TX buffer
     let bytes_to_write = data.len() as u16;
-    w5500_write_tx_buffer(spi, cs, tx_ptr, data).await?;
+    write_tx_buffer::<C, SPI>(spi, socket, tx_ptr, data).await?;
 
     // Step 3: Update TX write pointer
     let new_ptr = tx_ptr.wrapping_add(bytes_to_write);
     let new_ptr_bytes = new_ptr.to_be_bytes();
-    w5500_write_socket_register(spi, cs, REG_S0_TX_WR0, new_ptr_bytes[0]).await?;
-    w5500_write_socket_register(spi, cs, REG_S0_TX_WR0 + 1, new_ptr_bytes[1]).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_TX_WRITE_PTR, new_ptr_bytes[0]).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_TX_WRITE_PTR + 1, new_ptr_bytes[1]).await?;
 
     // Step 4: Send SEND command to transmit the data
-    w5500_write_socket_register(spi, cs, REG_S0_CR, SOCK_CMD_SEND).await?;
+    write_socket_register::<C, SPI>(spi, socket, C::SOCKET_COMMAND, SOCK_CMD_SEND).await?;
 
     // Step 5: Wait for SEND command to complete
     Timer::after_millis(10).await;
@@ -484,292 +457,70 @@ pub async fn write_tx_data(
 }
 
 // ============================================================================
-// W5500 Ethernet Functions
+// Ethernet Chip Register Access (generic over `Chip`)
 // ============================================================================
 
-/// W5500 Control Phase bits
-const CONTROL_PHASE_READ: u8 = 0x00;
-const CONTROL_PHASE_WRITE: u8 = 0x04;
-
-/// W5500 Block Select Bits (BSB) - Common Register block
-const BSB_COMMON_REG: u8 = 0x00;
-const BSB_SOCKET0_REG: u8 = 0x01;   // Socket 0 register block
-const BSB_SOCKET0_TX: u8 = 0x02;    // Socket 0 TX buffer
-const BSB_SOCKET0_RX: u8 = 0x03;    // Socket 0 RX buffer
-
-/// W5500 Common Registers
-const REG_VERSIONR: u16 = 0x0039;  // Chip Version Register (should be 0x04)
-const REG_SHAR0: u16 = 0x0009;     // Source Hardware Address (MAC) - 6 bytes
-const REG_SIPR0: u16 = 0x000F;     // Source IP Address - 4 bytes
-const REG_SUBR0: u16 = 0x0005;     // Subnet Mask - 4 bytes
-const REG_GAR0: u16 = 0x0001;      // Gateway Address - 4 bytes
-
-/// W5500 Socket 0 Registers
-const REG_S0_MR: u16 = 0x0000;      // Socket 0 Mode Register
-const REG_S0_CR: u16 = 0x0001;      // Socket 0 Command Register
-const REG_S0_SR: u16 = 0x0003;      // Socket 0 Status Register
-const REG_S0_PORT0: u16 = 0x0004;   // Socket 0 Source Port (2 bytes)
-const REG_S0_TX_FSR0: u16 = 0x0020; // Socket 0 TX Free Size (2 bytes)
-const REG_S0_TX_WR0: u16 = 0x0024;  // Socket 0 TX Write Pointer (2 bytes)
-const REG_S0_RX_RSR0: u16 = 0x0026; // Socket 0 RX Received Size (2 bytes)
-const REG_S0_RX_RD0: u16 = 0x0028;  // Socket 0 RX Read Pointer (2 bytes)
-
-/// Socket Mode Register values
-const SOCK_MODE_TCP: u8 = 0x01;     // TCP mode
+/// Socket Mode Register values (shared across the WIZnet family)
+const SOCK_MODE_TCP: u8 = 0x01; // TCP mode
 
 /// Socket Command Register values
-const SOCK_CMD_OPEN: u8 = 0x01;     // Open socket
-const SOCK_CMD_LISTEN: u8 = 0x02;   // Listen (TCP server)
-const SOCK_CMD_SEND: u8 = 0x20;     // Send data (complete TX operation)
-const SOCK_CMD_RECV: u8 = 0x40;     // Receive data (complete RX operation)
-const SOCK_CMD_CLOSE: u8 = 0x10;    // Close socket
+const SOCK_CMD_OPEN: u8 = 0x01; // Open socket
+const SOCK_CMD_LISTEN: u8 = 0x02; // Listen (TCP server)
+const SOCK_CMD_SEND: u8 = 0x20; // Send data (complete TX operation)
+const SOCK_CMD_RECV: u8 = 0x40; // Receive data (complete RX operation)
+const SOCK_CMD_CLOSE: u8 = 0x10; // Close socket
 
 /// Socket Status Register values
-const SOCK_STATUS_CLOSED: u8 = 0x00;
 const SOCK_STATUS_INIT: u8 = 0x13;
 const SOCK_STATUS_LISTEN: u8 = 0x14;
-const SOCK_STATUS_ESTABLISHED: u8 = 0x17;
-
-/// Read a single byte from W5500 common register
-///
-/// W5500 SPI Frame: [Address High] [Address Low] [Control] [Data...]
-async fn w5500_read_register(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-    address: u16,
-) -> Result<u8, ()> {
-    // W5500 control byte: [BSB(5 bits)][RWB(1 bit)][OM(2 bits)]
-    // BSB = Block Select, RWB = Read/Write, OM = Operation Mode (Variable Data Length)
-    let control = (BSB_COMMON_REG << 3) | CONTROL_PHASE_READ | 0x00; // VDM mode
-
-    let addr_high = (address >> 8) as u8;
-    let addr_low = (address & 0xFF) as u8;
-
-    let mut tx_buf = [addr_high, addr_low, control, 0x00]; // Last byte is dummy for read
-    let mut rx_buf = [0u8; 4];
-
-    info!("SPI TX: [{:02X} {:02X} {:02X} {:02X}]", tx_buf[0], tx_buf[1], tx_buf[2], tx_buf[3]);
-
-    // Select W5500 (CS low)
-    cs.set_low();
-    Timer::after_micros(1).await; // Small delay for CS setup
-
-    // Perform SPI transaction
-    let result = spi.transfer(&mut rx_buf, &tx_buf).await;
-
-    // Deselect W5500 (CS high)
-    Timer::after_micros(1).await;
-    cs.set_high();
 
-    info!("SPI RX: [{:02X} {:02X} {:02X} {:02X}]", rx_buf[0], rx_buf[1], rx_buf[2], rx_buf[3]);
-
-    match result {
-        Ok(_) => Ok(rx_buf[3]), // Data is in the 4th byte
-        Err(_) => Err(()),
-    }
+/// Read a single byte from a chip common register
+async fn read_register<C: Chip, SPI: SpiDevice>(spi: &mut SPI, offset: u16) -> Result<u8, ()> {
+    let mut buf = [0u8; 1];
+    C::bus_read(spi, C::common_addr(offset), &mut buf).await?;
+    Ok(buf[0])
 }
 
-/// Write multiple bytes to W5500 common register
-///
-/// W5500 SPI Frame: [Address High] [Address Low] [Control] [Data...]
-async fn w5500_write_register(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-    address: u16,
-    data: &[u8],
-) -> Result<(), ()> {
-    // W5500 control byte: [BSB(5 bits)][RWB(1 bit)][OM(2 bits)]
-    let control = (BSB_COMMON_REG << 3) | CONTROL_PHASE_WRITE | 0x00; // VDM mode
-
-    let addr_high = (address >> 8) as u8;
-    let addr_low = (address & 0xFF) as u8;
-
-    // Build TX buffer: [AddrH, AddrL, Control, ...data]
-    let mut tx_buf = [0u8; 32]; // Max 32 bytes for MAC + IP + subnet + gateway
-    let len = 3 + data.len();
-
-    tx_buf[0] = addr_high;
-    tx_buf[1] = addr_low;
-    tx_buf[2] = control;
-    tx_buf[3..len].copy_from_slice(data);
-
-    info!("W5500 WRITE to 0x{:04X}: {} bytes", address, data.len());
-
-    // Select W5500 (CS low)
-    cs.set_low();
-    Timer::after_micros(1).await;
-
-    // Perform SPI write
-    let result = spi.write(&tx_buf[..len]).await;
-
-    // Deselect W5500 (CS high)
-    Timer::after_micros(1).await;
-    cs.set_high();
-
-    match result {
-        Ok(_) => Ok(()),
-        Err(_) => Err(()),
-    }
+/// Write bytes to a chip common register
+async fn write_register<C: Chip, SPI: SpiDevice>(spi: &mut SPI, offset: u16, data: &[u8]) -> Result<(), ()> {
+    C::bus_write(spi, C::common_addr(offset), data).await
 }
 
-/// Read a single byte from W5500 socket register
-async fn w5500_read_socket_register(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-    address: u16,
-) -> Result<u8, ()> {
-    let control = (BSB_SOCKET0_REG << 3) | CONTROL_PHASE_READ | 0x00; // VDM mode
-
-    let addr_high = (address >> 8) as u8;
-    let addr_low = (address & 0xFF) as u8;
-
-    let tx_buf = [addr_high, addr_low, control, 0x00];
-    let mut rx_buf = [0u8; 4];
-
-    cs.set_low();
-    Timer::after_micros(1).await;
-    let result = spi.transfer(&mut rx_buf, &tx_buf).await;
-    Timer::after_micros(1).await;
-    cs.set_high();
-
-    match result {
-        Ok(_) => Ok(rx_buf[3]),
-        Err(_) => Err(()),
-    }
+/// Read a single byte from a socket register
+async fn read_socket_register<C: Chip, SPI: SpiDevice>(spi: &mut SPI, socket: u8, offset: u16) -> Result<u8, ()> {
+    let mut buf = [0u8; 1];
+    C::bus_read(spi, C::socket_addr(socket, offset), &mut buf).await?;
+    Ok(buf[0])
 }
 
-/// Write single byte to W5500 socket register
-async fn w5500_write_socket_register(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-    address: u16,
+/// Write a single byte to a socket register
+async fn write_socket_register<C: Chip, SPI: SpiDevice>(
+    spi: &mut SPI,
+    socket: u8,
+    offset: u16,
     data: u8,
 ) -> Result<(), ()> {
-    let control = (BSB_SOCKET0_REG << 3) | CONTROL_PHASE_WRITE | 0x00; // VDM mode
-
-    let addr_high = (address >> 8) as u8;
-    let addr_low = (address & 0xFF) as u8;
-
-    let tx_buf = [addr_high, addr_low, control, data];
-
-    cs.set_low();
-    Timer::after_micros(1).await;
-    let result = spi.write(&tx_buf).await;
-    Timer::after_micros(1).await;
-    cs.set_high();
-
-    match result {
-        Ok(_) => Ok(()),
-        Err(_) => Err(()),
-    }
+    C::bus_write(spi, C::socket_addr(socket, offset), &[data]).await
 }
 
-/// Write multiple bytes to W5500 socket register
-async fn w5500_write_socket_register_multi(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-    address: u16,
-    data: &[u8],
-) -> Result<(), ()> {
-    let control = (BSB_SOCKET0_REG << 3) | CONTROL_PHASE_WRITE | 0x00; // VDM mode
-
-    let addr_high = (address >> 8) as u8;
-    let addr_low = (address & 0xFF) as u8;
-
-    let mut tx_buf = [0u8; 16];
-    let len = 3 + data.len();
-
-    tx_buf[0] = addr_high;
-    tx_buf[1] = addr_low;
-    tx_buf[2] = control;
-    tx_buf[3..len].copy_from_slice(data);
-
-    cs.set_low();
-    Timer::after_micros(1).await;
-    let result = spi.write(&tx_buf[..len]).await;
-    Timer::after_micros(1).await;
-    cs.set_high();
-
-    match result {
-        Ok(_) => Ok(()),
-        Err(_) => Err(()),
-    }
-}
-
-/// Read data from W5500 RX buffer block
-///
-/// This reads from the Socket 0 RX buffer (BSB=0x03)
-async fn w5500_read_rx_buffer(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-    address: u16,
+/// Read data from a socket's RX buffer block
+async fn read_rx_buffer<C: Chip, SPI: SpiDevice>(
+    spi: &mut SPI,
+    socket: u8,
+    offset: u16,
     buffer: &mut [u8],
 ) -> Result<(), ()> {
-    let control = (BSB_SOCKET0_RX << 3) | CONTROL_PHASE_READ | 0x00; // VDM mode
-
-    let addr_high = (address >> 8) as u8;
-    let addr_low = (address & 0xFF) as u8;
-
-    // Build TX buffer: [AddrH, AddrL, Control, ...dummy bytes for reading]
-    let mut tx_buf = [0u8; 256]; // Max Modbus frame is 260 bytes
-    let len = 3 + buffer.len();
-
-    tx_buf[0] = addr_high;
-    tx_buf[1] = addr_low;
-    tx_buf[2] = control;
-    // Remaining bytes are dummy for read operation
-
-    let mut rx_buf = [0u8; 256];
-
-    cs.set_low();
-    Timer::after_micros(1).await;
-    let result = spi.transfer(&mut rx_buf[..len], &tx_buf[..len]).await;
-    Timer::after_micros(1).await;
-    cs.set_high();
-
-    match result {
-        Ok(_) => {
-            // Data starts at byte 3 (after address and control)
-            buffer.copy_from_slice(&rx_buf[3..len]);
-            Ok(())
-        }
-        Err(_) => Err(()),
-    }
+    C::bus_read(spi, C::rx_addr(socket, offset), buffer).await
 }
 
-/// Write data to W5500 TX buffer block
-///
-/// This writes to the Socket 0 TX buffer (BSB=0x02)
-async fn w5500_write_tx_buffer(
-    spi: &mut Spi<'_, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
-    cs: &mut Output<'_, peripherals::PB6>,
-    address: u16,
+/// Write data to a socket's TX buffer block
+async fn write_tx_buffer<C: Chip, SPI: SpiDevice>(
+    spi: &mut SPI,
+    socket: u8,
+    offset: u16,
     data: &[u8],
 ) -> Result<(), ()> {
-    let control = (BSB_SOCKET0_TX << 3) | CONTROL_PHASE_WRITE | 0x00; // VDM mode
-
-    let addr_high = (address >> 8) as u8;
-    let addr_low = (address & 0xFF) as u8;
-
-    // Build TX buffer: [AddrH, AddrL, Control, ...data]
-    let mut tx_buf = [0u8; 256]; // Max Modbus frame is 260 bytes
-    let len = 3 + data.len();
-
-    tx_buf[0] = addr_high;
-    tx_buf[1] = addr_low;
-    tx_buf[2] = control;
-    tx_buf[3..len].copy_from_slice(data);
-
-    cs.set_low();
-    Timer::after_micros(1).await;
-    let result = spi.write(&tx_buf[..len]).await;
-    Timer::after_micros(1).await;
-    cs.set_high();
-
-    match result {
-        Ok(_) => Ok(()),
-        Err(_) => Err(()),
-    }
+    C::bus_write(spi, C::tx_addr(socket, offset), data).await
 }
 
 // ============================================================================
@@ -777,7 +528,7 @@ async fn w5500_write_tx_buffer(
 // ============================================================================
 
 /// Modbus TCP MBAP Header (7 bytes)
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct MbapHeader {
     pub transaction_id: u16,  // 2 bytes - copied from request
     pub protocol_id: u16,     // 2 bytes - always 0x0000 for Modbus
@@ -819,6 +570,8 @@ impl MbapHeader {
 pub mod function_codes {
     pub const READ_HOLDING_REGISTERS: u8 = 0x03;
     pub const READ_INPUT_REGISTERS: u8 = 0x04;
+    pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+    pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
 }
 
 /// Modbus exception codes
@@ -826,39 +579,276 @@ pub mod exception_codes {
     pub const ILLEGAL_FUNCTION: u8 = 0x01;
     pub const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
     pub const ILLEGAL_DATA_VALUE: u8 = 0x03;
+    pub const SERVER_DEVICE_FAILURE: u8 = 0x04;
 }
 
-/// Parse Modbus TCP request and return (function_code, start_addr, count)
-pub fn parse_modbus_request(data: &[u8]) -> Result<(u8, u16, u16), u8> {
-    if data.len() < 5 {
-        return Err(exception_codes::ILLEGAL_DATA_VALUE);
+/// Build a Modbus exception response PDU (9 bytes: MBAP header + function
+/// code OR'd with 0x80 + the single exception byte).
+///
+/// `mbap` is the request's own header - the response echoes its
+/// transaction ID and unit ID verbatim, with `length` overwritten to 3
+/// (unit_id + function code + exception code). Call this instead of
+/// silently dropping a frame whenever `parse_modbus_request` or one of the
+/// `handle_*` functions returns `Err(exception_code)`, so a conformant
+/// master sees a spec-compliant error rather than a timeout.
+pub fn build_exception_response(
+    mbap: &MbapHeader,
+    function_code: u8,
+    exception_code: u8,
+    out: &mut [u8],
+) -> Result<usize, ()> {
+    if out.len() < 9 {
+        return Err(());
     }
+    let response_mbap = MbapHeader { length: 3, ..*mbap };
+    response_mbap.to_bytes(&mut out[0..7])?;
+    out[7] = function_code | 0x80;
+    out[8] = exception_code;
+    Ok(9)
+}
+
+/// A parsed Modbus PDU, borrowing its write payload (if any) from the
+/// request buffer rather than copying it.
+pub enum ModbusRequest<'a> {
+    /// FC03/FC04: read `count` registers starting at `start_addr`.
+    Read { function_code: u8, start_addr: u16, count: u16 },
+    /// FC06: write `value` to `addr`.
+    WriteSingle { addr: u16, value: u16 },
+    /// FC16: write `count` big-endian register values (`2 * count` bytes)
+    /// starting at `start_addr`.
+    WriteMultiple { start_addr: u16, count: u16, values: &'a [u8] },
+}
+
+/// Reassembly state of a [`ModbusFrameAssembler`]
+enum FrameAssemblyState {
+    /// Fewer than 6 bytes (transaction id + protocol id + length) buffered
+    WaitingHeader,
+    /// Header parsed; waiting for `remaining` more bytes (unit id + PDU)
+    WaitingBody { remaining: usize },
+    /// A full frame is sitting in `buffer`, ready to be handed back
+    Complete,
+}
+
+/// Reassembles Modbus TCP frames out of arbitrarily segmented TCP reads
+///
+/// A `read`/`recv_slice` call has no obligation to hand back exactly one
+/// MBAP+PDU frame - TCP segmentation or Nagle batching can split one frame
+/// across reads or coalesce two pipelined requests into one. This tracks
+/// just enough state (buffered bytes plus the MBAP `length` field once it's
+/// arrived) to know when a full frame is available, independent of how it
+/// was chunked on the wire.
+pub struct ModbusFrameAssembler {
+    buffer: heapless::Vec<u8, 260>,
+    overflow: heapless::Vec<u8, 260>,
+    state: FrameAssemblyState,
+}
+
+impl ModbusFrameAssembler {
+    pub fn new() -> Self {
+        ModbusFrameAssembler { buffer: heapless::Vec::new(), overflow: heapless::Vec::new(), state: FrameAssemblyState::WaitingHeader }
+    }
+
+    /// Drop whatever's buffered and start waiting for a new frame from
+    /// scratch - call this whenever the underlying connection is torn down
+    /// and re-established, so a partial frame from the old connection can
+    /// never be stitched onto the new one's bytes.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.overflow.clear();
+        self.state = FrameAssemblyState::WaitingHeader;
+    }
+
+    /// Feed newly-read bytes in, returning the next complete MBAP+PDU frame
+    /// once enough bytes have accumulated for it.
+    ///
+    /// If `bytes` contains more than one frame (two pipelined requests
+    /// arriving in the same segment), only the first is returned - call
+    /// again with an empty slice to drain the rest already buffered in
+    /// `overflow` before reading more off the socket.
+    pub fn push(&mut self, bytes: &[u8]) -> Option<&[u8]> {
+        if let FrameAssemblyState::Complete = self.state {
+            self.buffer.clear();
+            self.state = FrameAssemblyState::WaitingHeader;
+            if !self.overflow.is_empty() {
+                self.buffer.extend_from_slice(&core::mem::take(&mut self.overflow)).ok();
+            }
+        }
+
+        if self.buffer.extend_from_slice(bytes).is_err() {
+            // A frame this big can't be valid Modbus - resync from scratch
+            // rather than getting stuck unable to ever buffer a full frame.
+            self.reset();
+            return None;
+        }
+
+        if let FrameAssemblyState::WaitingHeader = self.state {
+            if self.buffer.len() < 6 {
+                return None;
+            }
+            let length = u16::from_be_bytes([self.buffer[4], self.buffer[5]]) as usize;
+            self.state = FrameAssemblyState::WaitingBody { remaining: length };
+        }
+
+        let frame_len = match self.state {
+            FrameAssemblyState::WaitingBody { remaining } => 6 + remaining,
+            _ => return None,
+        };
 
-    let function_code = data[0];
-    let start_addr = u16::from_be_bytes([data[1], data[2]]);
-    let count = u16::from_be_bytes([data[3], data[4]]);
+        if self.buffer.len() < frame_len {
+            return None;
+        }
 
-    // Validate function code
-    if function_code != function_codes::READ_HOLDING_REGISTERS
-        && function_code != function_codes::READ_INPUT_REGISTERS {
-        return Err(exception_codes::ILLEGAL_FUNCTION);
+        if self.buffer.len() > frame_len {
+            self.overflow.extend_from_slice(&self.buffer[frame_len..]).ok();
+            self.buffer.truncate(frame_len);
+        }
+        self.state = FrameAssemblyState::Complete;
+        Some(&self.buffer[..frame_len])
     }
+}
 
-    // Validate count (max 125 registers for Modbus TCP)
-    if count == 0 || count > 125 {
+impl Default for ModbusFrameAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse a Modbus TCP PDU (function code + payload, no MBAP header)
+///
+/// Validates each function code's own framing before handing back a
+/// borrowed `ModbusRequest`: FC03/FC04 reject a zero or >125 register
+/// count, FC16 rejects a byte count that doesn't match `count * 2` or
+/// that overruns the buffer, all with `ILLEGAL_DATA_VALUE` - register
+/// address bounds are `handle_write_registers`'/`handle_read_registers`'
+/// job, since this function doesn't see the holding-register map.
+pub fn parse_modbus_request(data: &[u8]) -> Result<ModbusRequest<'_>, u8> {
+    if data.is_empty() {
         return Err(exception_codes::ILLEGAL_DATA_VALUE);
     }
 
-    Ok((function_code, start_addr, count))
+    match data[0] {
+        fc @ (function_codes::READ_HOLDING_REGISTERS | function_codes::READ_INPUT_REGISTERS) => {
+            if data.len() < 5 {
+                return Err(exception_codes::ILLEGAL_DATA_VALUE);
+            }
+            let start_addr = u16::from_be_bytes([data[1], data[2]]);
+            let count = u16::from_be_bytes([data[3], data[4]]);
+            if count == 0 || count > 125 {
+                return Err(exception_codes::ILLEGAL_DATA_VALUE);
+            }
+            Ok(ModbusRequest::Read { function_code: fc, start_addr, count })
+        }
+        function_codes::WRITE_SINGLE_REGISTER => {
+            if data.len() < 5 {
+                return Err(exception_codes::ILLEGAL_DATA_VALUE);
+            }
+            let addr = u16::from_be_bytes([data[1], data[2]]);
+            let value = u16::from_be_bytes([data[3], data[4]]);
+            Ok(ModbusRequest::WriteSingle { addr, value })
+        }
+        function_codes::WRITE_MULTIPLE_REGISTERS => {
+            if data.len() < 6 {
+                return Err(exception_codes::ILLEGAL_DATA_VALUE);
+            }
+            let start_addr = u16::from_be_bytes([data[1], data[2]]);
+            let count = u16::from_be_bytes([data[3], data[4]]);
+            let byte_count = data[5] as usize;
+            if count == 0 || count > 123 || byte_count != count as usize * 2 || data.len() < 6 + byte_count {
+                return Err(exception_codes::ILLEGAL_DATA_VALUE);
+            }
+            Ok(ModbusRequest::WriteMultiple { start_addr, count, values: &data[6..6 + byte_count] })
+        }
+        _ => Err(exception_codes::ILLEGAL_FUNCTION),
+    }
+}
+
+/// Parse `pdu`, dispatch it against `sensor_data`/`device_config`, and build
+/// the response frame into `response`, returning its length.
+///
+/// This is the one copy of the FC03/FC04/FC06/FC16 dispatch-and-respond
+/// logic - `backend::service_frame` (W5500 offload sockets),
+/// `macraw::service_modbus` (smoltcp/MACRAW), and `net::run_modbus_server`
+/// (embassy-net) all parse an MBAP header themselves (their transports
+/// differ too much there to share), then call this with the header and PDU
+/// to get the exact same response bytes, and only differ in how they
+/// actually write those bytes to the wire. A fix to a register handler or
+/// the read/write framing only has to be made here, not three times.
+///
+/// Returns `None` only if `response` is too small to hold even an MBAP
+/// header (7 bytes) - with the 260-byte scratch buffers every transport
+/// actually passes in, that never happens.
+pub fn dispatch_modbus_request(
+    mbap: &MbapHeader,
+    pdu: &[u8],
+    sensor_data: &SensorData,
+    device_config: &mut DeviceConfig,
+    response: &mut [u8; 260],
+) -> Option<usize> {
+    if mbap.to_bytes(&mut response[0..7]).is_err() {
+        return None;
+    }
+
+    match parse_modbus_request(pdu) {
+        Ok(ModbusRequest::Read { function_code, start_addr, count }) => {
+            let mut pos = 7;
+            response[pos] = function_code;
+            pos += 1;
+            response[pos] = (count * 2) as u8;
+            pos += 1;
+
+            let read_result = if function_code == function_codes::READ_INPUT_REGISTERS {
+                handle_read_input_registers(start_addr, count, sensor_data, device_config.word_order, &mut response[pos..])
+            } else {
+                handle_read_registers(start_addr, count, sensor_data, device_config.word_order, &mut response[pos..])
+            };
+            match read_result {
+                Ok(data_len) => {
+                    pos += data_len;
+                    let response_length = (pos - 6) as u16; // unit_id + PDU
+                    response[4..6].copy_from_slice(&response_length.to_be_bytes());
+                    Some(pos)
+                }
+                Err(exception_code) => build_exception_response(mbap, function_code, exception_code, response).ok(),
+            }
+        }
+        Ok(ModbusRequest::WriteSingle { addr, value }) => match handle_write_registers(addr, 1, &value.to_be_bytes(), device_config) {
+            Ok(()) => {
+                // FC06 echoes the request's address and value verbatim
+                response[7] = function_codes::WRITE_SINGLE_REGISTER;
+                response[8..10].copy_from_slice(&addr.to_be_bytes());
+                response[10..12].copy_from_slice(&value.to_be_bytes());
+                response[4..6].copy_from_slice(&6u16.to_be_bytes()); // unit_id + FC + addr + value
+                Some(12)
+            }
+            Err(exception_code) => build_exception_response(mbap, function_codes::WRITE_SINGLE_REGISTER, exception_code, response).ok(),
+        },
+        Ok(ModbusRequest::WriteMultiple { start_addr, count, values }) => match handle_write_registers(start_addr, count, values, device_config) {
+            Ok(()) => {
+                // FC16 echoes the request's start address and quantity
+                response[7] = function_codes::WRITE_MULTIPLE_REGISTERS;
+                response[8..10].copy_from_slice(&start_addr.to_be_bytes());
+                response[10..12].copy_from_slice(&count.to_be_bytes());
+                response[4..6].copy_from_slice(&6u16.to_be_bytes()); // unit_id + FC + addr + count
+                Some(12)
+            }
+            Err(exception_code) => build_exception_response(mbap, function_codes::WRITE_MULTIPLE_REGISTERS, exception_code, response).ok(),
+        },
+        Err(exception_code) => {
+            let function_code = pdu.first().copied().unwrap_or(0);
+            build_exception_response(mbap, function_code, exception_code, response).ok()
+        }
+    }
 }
 
 /// Sensor data structure for Modbus register mapping
 #[derive(Clone, Copy)]
 pub struct SensorData {
-    pub temperature: f32,    // Celsius
-    pub humidity: f32,       // Percentage (0-100)
-    pub status: u16,         // Status code (0=OK, 1=Error, etc.)
-    pub uptime: u32,         // Uptime in seconds
+    pub temperature: f32,     // Celsius
+    pub humidity: f32,        // Percentage (0-100)
+    pub pressure: f32,        // hPa - 0.0 on sensors without a pressure channel (see `crate::sensor::Measurement`)
+    pub gas_resistance: f32,  // Ohms - 0.0 on sensors without a gas channel
+    pub status: u16,          // Status code (0=OK, 1=Error, etc.)
+    pub uptime: u32,          // Uptime in seconds
 }
 
 impl Default for SensorData {
@@ -866,25 +856,64 @@ impl Default for SensorData {
         SensorData {
             temperature: 25.5,   // Mock temperature
             humidity: 60.0,      // Mock humidity
+            pressure: 0.0,
+            gas_resistance: 0.0,
             status: status::OK,
             uptime: 0,
         }
     }
 }
 
+/// Writable actuator/config registers (device control, not sensor data)
+///
+/// Exposed starting at Modbus register 40100 (see `registers`), so a master
+/// can command the board rather than only read it.
+#[derive(Clone, Copy)]
+pub struct DeviceConfig {
+    pub heater_enable: bool,
+    pub measurement_interval_s: u16,
+    pub display_mode: u16,
+    pub word_order: WordOrder,
+    /// Set whenever a write touches this block; the main loop clears it by
+    /// committing the current config to flash - see `crate::flash_store`.
+    pub dirty: bool,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        DeviceConfig {
+            heater_enable: false,
+            measurement_interval_s: 2,
+            display_mode: 0,
+            word_order: WordOrder::AbCd,
+            dirty: false,
+        }
+    }
+}
+
 /// Handle Modbus FC03 (Read Holding Registers)
 ///
-/// Maps Modbus register addresses to sensor data:
-/// - 40001-40002: Temperature (f32, IEEE 754)
-/// - 40003-40004: Humidity (f32, IEEE 754)
-/// - 40005: Status (u16)
-/// - 40006-40007: Uptime (u32)
+/// Maps Modbus register addresses to status/derived values:
+/// - 40001: Status (u16)
+/// - 40002-40003: Uptime (u32)
+/// - 40004-40006: Reserved
+///
+/// The writable actuator/config block at 40100+ (see
+/// `handle_write_registers`) is not mapped here: reading it returns
+/// `ILLEGAL_DATA_ADDRESS`, like any other address past 40006.
+///
+/// Measured values (temperature, humidity) live in the input-register space
+/// instead - see `handle_read_input_registers`.
+///
+/// `word_order` (from `DeviceConfig.word_order`) governs how the 32-bit
+/// uptime value is split across its two registers - see `WordOrder`.
 ///
 /// Note: Modbus uses 1-based addressing, but we convert to 0-based internally
 pub fn handle_read_registers(
     start_addr: u16,
     count: u16,
     sensor_data: &SensorData,
+    word_order: WordOrder,
     response_buffer: &mut [u8],
 ) -> Result<usize, u8> {
     // Modbus register addresses are 1-based (40001, 40002, etc.)
@@ -897,37 +926,19 @@ pub fn handle_read_registers(
 
         // Map register address to data
         let reg_value = match reg_addr {
-            // Temperature: registers 0-1 (Modbus 40001-40002)
-            0 => {
-                let temp_regs = f32_to_registers(sensor_data.temperature);
-                temp_regs[0]
-            }
+            // Status: register 0 (Modbus 40001)
+            0 => sensor_data.status,
+            // Uptime: registers 1-2 (Modbus 40002-40003)
             1 => {
-                let temp_regs = f32_to_registers(sensor_data.temperature);
-                temp_regs[1]
-            }
-            // Humidity: registers 2-3 (Modbus 40003-40004)
-            2 => {
-                let hum_regs = f32_to_registers(sensor_data.humidity);
-                hum_regs[0]
-            }
-            3 => {
-                let hum_regs = f32_to_registers(sensor_data.humidity);
-                hum_regs[1]
-            }
-            // Status: register 4 (Modbus 40005)
-            4 => sensor_data.status,
-            // Uptime: registers 5-6 (Modbus 40006-40007)
-            5 => {
-                let uptime_regs = u32_to_registers(sensor_data.uptime);
+                let uptime_regs = u32_to_registers(sensor_data.uptime, word_order);
                 uptime_regs[0]
             }
-            6 => {
-                let uptime_regs = u32_to_registers(sensor_data.uptime);
+            2 => {
+                let uptime_regs = u32_to_registers(sensor_data.uptime, word_order);
                 uptime_regs[1]
             }
-            // Reserved: registers 7-9 (Modbus 40008-40010)
-            7..=9 => 0x0000,
+            // Reserved: registers 3-5 (Modbus 40004-40006)
+            3..=5 => 0x0000,
             // Out of range
             _ => return Err(exception_codes::ILLEGAL_DATA_ADDRESS),
         };
@@ -944,19 +955,126 @@ pub fn handle_read_registers(
 }
 
 /// Handle Modbus FC04 (Read Input Registers)
-// TODO: Implement FC04 handler (similar to FC03)
+///
+/// Maps Modbus input-register addresses to live measured values:
+/// - 30001-30002: Temperature (f32, IEEE 754)
+/// - 30003-30004: Humidity (f32, IEEE 754)
+/// - 30005-30006: Pressure (f32, hPa - 0.0 without a BME680 fitted)
+/// - 30007-30008: Gas resistance (f32, Ohms - 0.0 without a BME680 fitted)
+///
+/// Any future sensor channel extends this map rather than the
+/// holding-register one, keeping measured values and configurable
+/// setpoints/derived values in their own conventional address spaces.
+///
+/// `word_order` (from `DeviceConfig.word_order`) governs how each f32 is
+/// split across its two registers - see `WordOrder`.
+pub fn handle_read_input_registers(
+    start_addr: u16,
+    count: u16,
+    sensor_data: &SensorData,
+    word_order: WordOrder,
+    response_buffer: &mut [u8],
+) -> Result<usize, u8> {
+    let mut pos = 0;
+
+    for i in 0..count {
+        let reg_addr = start_addr + i;
+
+        let reg_value = match reg_addr {
+            // Temperature: registers 0-1 (Modbus 30001-30002)
+            0 => f32_to_registers(sensor_data.temperature, word_order)[0],
+            1 => f32_to_registers(sensor_data.temperature, word_order)[1],
+            // Humidity: registers 2-3 (Modbus 30003-30004)
+            2 => f32_to_registers(sensor_data.humidity, word_order)[0],
+            3 => f32_to_registers(sensor_data.humidity, word_order)[1],
+            // Pressure: registers 4-5 (Modbus 30005-30006)
+            4 => f32_to_registers(sensor_data.pressure, word_order)[0],
+            5 => f32_to_registers(sensor_data.pressure, word_order)[1],
+            // Gas resistance: registers 6-7 (Modbus 30007-30008)
+            6 => f32_to_registers(sensor_data.gas_resistance, word_order)[0],
+            7 => f32_to_registers(sensor_data.gas_resistance, word_order)[1],
+            // Out of range
+            _ => return Err(exception_codes::ILLEGAL_DATA_ADDRESS),
+        };
+
+        if pos + 2 > response_buffer.len() {
+            return Err(exception_codes::ILLEGAL_DATA_VALUE);
+        }
+        response_buffer[pos..pos + 2].copy_from_slice(&reg_value.to_be_bytes());
+        pos += 2;
+    }
+
+    Ok(pos)
+}
+
+/// Handle Modbus FC06/FC16 (Write Single/Multiple Registers)
+///
+/// `values` holds `count` big-endian register values (2 bytes each - for
+/// FC06 that's a single value). Only the actuator/config block starting at
+/// 40100 is writable; any other address - including the read-only sensor
+/// registers above - is rejected with `ILLEGAL_DATA_ADDRESS`.
+pub fn handle_write_registers(
+    start_addr: u16,
+    count: u16,
+    values: &[u8],
+    config: &mut DeviceConfig,
+) -> Result<(), u8> {
+    const HEATER_ENABLE_ADDR: u16 = registers::HEATER_ENABLE_REGISTER - registers::STATUS_REGISTER;
+    const MEASUREMENT_INTERVAL_ADDR: u16 = registers::MEASUREMENT_INTERVAL_REGISTER - registers::STATUS_REGISTER;
+    const DISPLAY_MODE_ADDR: u16 = registers::DISPLAY_MODE_REGISTER - registers::STATUS_REGISTER;
+    const WORD_ORDER_ADDR: u16 = registers::WORD_ORDER_REGISTER - registers::STATUS_REGISTER;
+
+    if values.len() < count as usize * 2 {
+        return Err(exception_codes::ILLEGAL_DATA_VALUE);
+    }
+
+    for i in 0..count {
+        let reg_addr = start_addr + i;
+        let value = u16::from_be_bytes([values[i as usize * 2], values[i as usize * 2 + 1]]);
+
+        match reg_addr {
+            HEATER_ENABLE_ADDR => config.heater_enable = value != 0,
+            MEASUREMENT_INTERVAL_ADDR => {
+                if value == 0 {
+                    return Err(exception_codes::ILLEGAL_DATA_VALUE);
+                }
+                config.measurement_interval_s = value;
+            }
+            DISPLAY_MODE_ADDR => config.display_mode = value,
+            WORD_ORDER_ADDR => {
+                config.word_order = WordOrder::from_register(value)
+                    .ok_or(exception_codes::ILLEGAL_DATA_VALUE)?;
+            }
+            _ => return Err(exception_codes::ILLEGAL_DATA_ADDRESS),
+        }
+    }
+
+    // Every successful write leaves the in-memory config ahead of whatever's
+    // in flash - flag it so the main loop schedules a commit (see
+    // `flash_store::save`) instead of writing on every single request.
+    config.dirty = true;
+
+    Ok(())
+}
+
+// Per-socket connection-manager servicing (status/recv/send dispatch) now
+// lives in `backend::service_socket`, generic over any `EthernetBackend`
+// rather than a specific `Chip`/`SpiDevice` pair. The helpers above remain
+// here as the W5500's own building blocks, used by `backend::W5500Backend`.
 
 // ============================================================================
 // Sensor Tasks
 // ============================================================================
 
-/// Initialize SHT31-D sensor on I2C1
+/// Bring up the I2C1 bus shared by the environmental sensor and the OLED
+/// display.
 ///
 /// Pins: PB8 (SCL), PB9 (SDA) - must be configured as open-drain
 ///
-/// Note: SHT31-D uses I2C address 0x44 (default)
-pub async fn init_sht3x() -> I2c<'static, peripherals::I2C1, peripherals::DMA1_CH6, peripherals::DMA1_CH0> {
-    info!("Initializing SHT31-D sensor on I2C1");
+/// Which sensor chip is actually listening on this bus is no longer assumed
+/// here - hand the returned bus to [`crate::sensor::probe`] instead.
+pub async fn init_i2c1() -> I2c<'static, peripherals::I2C1, peripherals::DMA1_CH6, peripherals::DMA1_CH0> {
+    info!("Initializing I2C1 (environmental sensor bus)");
 
     // Get peripherals
     let p = unsafe { embassy_stm32::Peripherals::steal() };
@@ -968,7 +1086,7 @@ pub async fn init_sht3x() -> I2c<'static, peripherals::I2C1, peripherals::DMA1_C
     i2c_config.scl_pullup = false;  // Disable internal pull-ups (use external)
 
     info!("Configuring I2C1: SCL=PB8, SDA=PB9 (open-drain mode)");
-    let mut i2c = I2c::new(
+    let i2c = I2c::new(
         p.I2C1,
         p.PB8,  // SCL (D15 on NUCLEO)
         p.PB9,  // SDA (D14 on NUCLEO)
@@ -982,84 +1100,25 @@ pub async fn init_sht3x() -> I2c<'static, peripherals::I2C1, peripherals::DMA1_C
     // Wait for sensor power-on (sensor needs time to stabilize)
     Timer::after_millis(100).await;
 
-    // Test communication with soft reset command
-    info!("Sending soft reset to SHT31-D...");
-    let reset_cmd = [0x30, 0xA2];
-    match i2c.write(0x44, &reset_cmd).await {
-        Ok(_) => {
-            info!("Soft reset sent successfully");
-            // Wait for reset to complete
-            Timer::after_millis(20).await;
-        }
-        Err(_) => {
-            warn!("Failed to send soft reset - I2C communication error");
-            warn!("Check wiring: SCL=PB8, SDA=PB9, VCC=3.3V, GND=GND");
-            warn!("Ensure 4.7kΩ pull-up resistors are present on SCL and SDA");
-        }
-    }
-
-    info!("SHT31-D sensor initialized");
     i2c
 }
 
-/// Read temperature and humidity from SHT31-D sensor
-///
-/// Returns (temperature_celsius, humidity_percent) or error
-///
-/// Uses high repeatability measurement (most accurate)
-pub async fn read_sht3x(
-    i2c: &mut I2c<'_, peripherals::I2C1, peripherals::DMA1_CH6, peripherals::DMA1_CH0>
-) -> Result<(f32, f32), ()> {
-    // SHT31-D command: 0x2400 (High repeatability measurement, clock stretching disabled)
-    let cmd = [0x24, 0x00];
-
-    // Step 1: Send measurement command
-    if let Err(_) = i2c.write(0x44, &cmd).await {
-        warn!("Failed to send measurement command to SHT31-D");
-        return Err(());
-    }
-
-    // Step 2: Wait for measurement to complete (CRITICAL - must wait 20ms minimum)
-    Timer::after_millis(20).await;
-
-    // Step 3: Read 6 bytes (Temp MSB/LSB + CRC, Humidity MSB/LSB + CRC)
-    let mut data = [0u8; 6];
-    match i2c.read(0x44, &mut data).await {
-        Ok(_) => {
-            // Extract temperature (first 2 bytes, ignore CRC at data[2])
-            let temp_raw = u16::from_be_bytes([data[0], data[1]]);
-            // Extract humidity (bytes 3-4, ignore CRC at data[5])
-            let hum_raw = u16::from_be_bytes([data[3], data[4]]);
-
-            // Convert to physical units (SHT31-D datasheet formulas)
-            let temp_c = -45.0 + 175.0 * (temp_raw as f32 / 65535.0);
-            let hum_pct = 100.0 * (hum_raw as f32 / 65535.0);
-
-            Ok((temp_c, hum_pct))
-        }
-        Err(_) => {
-            warn!("Failed to read SHT31-D sensor data");
-            Err(())
-        }
-    }
-}
-
 // ============================================================================
 // OLED Display Tasks
 // ============================================================================
 
 use embassy_stm32::dma::NoDma;
 
-/// OLED Display type (using blocking I2C without DMA on I2C1, shared with SHT31-D)
+/// OLED Display type (using blocking I2C without DMA on I2C1, shared with the environmental sensor)
 pub type OledDisplay = Ssd1306<
     I2CInterface<I2c<'static, peripherals::I2C1, NoDma, NoDma>>,
     DisplaySize128x64,
     BufferedGraphicsMode<DisplaySize128x64>
 >;
 
-/// Initialize SSD1306 OLED display on I2C1 (shared with SHT31-D)
+/// Initialize SSD1306 OLED display on I2C1 (shared with the environmental sensor)
 ///
-/// Pins: PB8 (SCL), PB9 (SDA) - same physical bus as SHT31-D sensor
+/// Pins: PB8 (SCL), PB9 (SDA) - same physical bus as the sensor
 /// Address: 0x3C (default for most SSD1306 displays)
 ///
 /// NOTE: This steals the I2C1 peripheral a second time without DMA.
@@ -1070,7 +1129,7 @@ pub async fn init_oled() -> OledDisplay {
     // Steal peripherals again for OLED
     let p = unsafe { embassy_stm32::Peripherals::steal() };
 
-    // Configure I2C1 at 100 kHz (standard mode, same as SHT31-D)
+    // Configure I2C1 at 100 kHz (standard mode, same as the sensor bus)
     // Note: Using blocking I2C (no DMA) for ssd1306 compatibility
     let mut i2c_config = I2cConfig::default();
     i2c_config.sda_pullup = false;  // Use external pull-ups
@@ -1079,8 +1138,8 @@ pub async fn init_oled() -> OledDisplay {
     info!("Configuring I2C1 for OLED: SCL=PB8, SDA=PB9 (blocking mode, no DMA)");
     let i2c = I2c::new(
         p.I2C1,
-        p.PB8,  // SCL (D15 on NUCLEO) - shared with SHT31-D
-        p.PB9,  // SDA (D14 on NUCLEO) - shared with SHT31-D
+        p.PB8,  // SCL (D15 on NUCLEO) - shared with the sensor
+        p.PB9,  // SDA (D14 on NUCLEO) - shared with the sensor
         I2c1Irqs,
         NoDma,  // No TX DMA for blocking I2C
         NoDma,  // No RX DMA for blocking I2C
@@ -1147,6 +1206,7 @@ pub fn update_display(
     board_id: &str,
     ip: [u8; 4],
     connected: bool,
+    display_mode: u16,
 ) {
     let text_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
 
@@ -1174,12 +1234,17 @@ pub fn update_display(
     let _ = write!(text, "H: {:.1}%", sensor_data.humidity);
     let _ = Text::new(&text, Point::new(0, 46), text_style).draw(display);
 
-    // Line 5: Connection status
+    // Line 5: connection status (mode 0, the default) or uptime (mode 1+) -
+    // set remotely via the DISPLAY_MODE_REGISTER (40102) Modbus write
     text.clear();
-    if connected {
-        let _ = write!(text, "CONNECTED");
+    if display_mode == 0 {
+        if connected {
+            let _ = write!(text, "CONNECTED");
+        } else {
+            let _ = write!(text, "LISTENING");
+        }
     } else {
-        let _ = write!(text, "LISTENING");
+        let _ = write!(text, "Up: {}s", sensor_data.uptime);
     }
     let _ = Text::new(&text, Point::new(0, 58), text_style).draw(display);
 
@@ -1212,16 +1277,169 @@ pub fn update_display(
 // Helper Functions
 // ============================================================================
 
-/// Convert f32 to two u16 registers (IEEE 754)
-pub fn f32_to_registers(value: f32) -> [u16; 2] {
-    let bytes = value.to_be_bytes();
-    [
-        u16::from_be_bytes([bytes[0], bytes[1]]),
-        u16::from_be_bytes([bytes[2], bytes[3]]),
-    ]
+/// Convert f32 to two u16 registers (IEEE 754), in the requested word order
+pub fn f32_to_registers(value: f32, word_order: WordOrder) -> [u16; 2] {
+    u32_to_registers(value.to_bits(), word_order)
 }
 
-/// Convert u32 to two u16 registers
-pub fn u32_to_registers(value: u32) -> [u16; 2] {
-    [(value >> 16) as u16, (value & 0xFFFF) as u16]
+/// Convert u32 to two u16 registers, in the requested word order
+///
+/// `AbCd` (high word first, each word big-endian) is the conventional
+/// Modbus layout; the other three variants swap the word order, the byte
+/// order within each word, or both, to match whichever convention the
+/// connecting master expects - see [`WordOrder`].
+pub fn u32_to_registers(value: u32, word_order: WordOrder) -> [u16; 2] {
+    let hi = (value >> 16) as u16;
+    let lo = (value & 0xFFFF) as u16;
+    match word_order {
+        WordOrder::AbCd => [hi, lo],
+        WordOrder::CdAb => [lo, hi],
+        WordOrder::BaDc => [hi.swap_bytes(), lo.swap_bytes()],
+        WordOrder::DcBa => [lo.swap_bytes(), hi.swap_bytes()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mbap(transaction_id: u16, length: u16) -> MbapHeader {
+        MbapHeader { transaction_id, protocol_id: 0, length, unit_id: 0 }
+    }
+
+    // ---- ModbusFrameAssembler ----
+
+    #[test]
+    fn assembler_returns_frame_split_across_two_pushes() {
+        let mut asm = ModbusFrameAssembler::new();
+        // FC03 read request: MBAP (length=6) + unit_id + function + addr + count
+        let frame = [0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x00, 0x03, 0x00, 0x00, 0x00, 0x0A];
+
+        assert_eq!(asm.push(&frame[..4]), None, "incomplete header shouldn't yield a frame");
+        assert_eq!(asm.push(&frame[4..9]), None, "incomplete body shouldn't yield a frame");
+        let got = asm.push(&frame[9..]).expect("full frame should be available now");
+        assert_eq!(got, &frame[..]);
+    }
+
+    #[test]
+    fn assembler_drains_pipelined_frames_from_overflow() {
+        let mut asm = ModbusFrameAssembler::new();
+        let frame_a = [0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x00, 0x03, 0x00, 0x00, 0x00, 0x0A];
+        let frame_b = [0x00, 0x02, 0x00, 0x00, 0x00, 0x06, 0x00, 0x03, 0x00, 0x01, 0x00, 0x05];
+
+        let mut both = heapless::Vec::<u8, 260>::new();
+        both.extend_from_slice(&frame_a).unwrap();
+        both.extend_from_slice(&frame_b).unwrap();
+
+        let first = asm.push(&both).expect("first frame should be ready immediately");
+        assert_eq!(first, &frame_a[..]);
+
+        // The second frame was buffered in `overflow`; draining it doesn't
+        // require feeding any more bytes in.
+        let second = asm.push(&[]).expect("second frame should drain from overflow");
+        assert_eq!(second, &frame_b[..]);
+    }
+
+    #[test]
+    fn assembler_resyncs_after_an_oversized_frame() {
+        let mut asm = ModbusFrameAssembler::new();
+        let garbage = [0xFFu8; 512]; // far larger than the 260-byte buffer
+        assert_eq!(asm.push(&garbage), None);
+
+        // After the overflow-triggered reset, a well-formed frame still parses.
+        let frame = [0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x00, 0x03, 0x00, 0x00, 0x00, 0x0A];
+        let got = asm.push(&frame).expect("assembler should have resynced");
+        assert_eq!(got, &frame[..]);
+    }
+
+    // ---- parse_modbus_request ----
+
+    fn expect_err(result: Result<ModbusRequest<'_>, u8>, expected: u8) {
+        match result {
+            Err(code) => assert_eq!(code, expected),
+            Ok(_) => panic!("expected exception code 0x{expected:02X}, got Ok"),
+        }
+    }
+
+    #[test]
+    fn parse_read_holding_registers_rejects_zero_count() {
+        let pdu = [function_codes::READ_HOLDING_REGISTERS, 0x00, 0x00, 0x00, 0x00];
+        expect_err(parse_modbus_request(&pdu), exception_codes::ILLEGAL_DATA_VALUE);
+    }
+
+    #[test]
+    fn parse_read_holding_registers_rejects_count_over_125() {
+        let pdu = [function_codes::READ_HOLDING_REGISTERS, 0x00, 0x00, 0x00, 126];
+        expect_err(parse_modbus_request(&pdu), exception_codes::ILLEGAL_DATA_VALUE);
+    }
+
+    #[test]
+    fn parse_read_holding_registers_accepts_valid_request() {
+        let pdu = [function_codes::READ_HOLDING_REGISTERS, 0x00, 0x05, 0x00, 0x02];
+        match parse_modbus_request(&pdu).expect("should parse") {
+            ModbusRequest::Read { function_code, start_addr, count } => {
+                assert_eq!(function_code, function_codes::READ_HOLDING_REGISTERS);
+                assert_eq!(start_addr, 5);
+                assert_eq!(count, 2);
+            }
+            _ => panic!("wrong variant"),
+        }
+    }
+
+    #[test]
+    fn parse_write_multiple_rejects_byte_count_mismatch() {
+        // count=2 registers claims byte_count=3, which can't be 2*2
+        let pdu = [function_codes::WRITE_MULTIPLE_REGISTERS, 0x00, 0x00, 0x00, 0x02, 0x03, 0xAA, 0xBB, 0xCC];
+        expect_err(parse_modbus_request(&pdu), exception_codes::ILLEGAL_DATA_VALUE);
+    }
+
+    #[test]
+    fn parse_write_multiple_rejects_buffer_shorter_than_declared_payload() {
+        // byte_count correctly matches count*2, but the buffer is truncated
+        let pdu = [function_codes::WRITE_MULTIPLE_REGISTERS, 0x00, 0x00, 0x00, 0x02, 0x04, 0xAA, 0xBB];
+        expect_err(parse_modbus_request(&pdu), exception_codes::ILLEGAL_DATA_VALUE);
+    }
+
+    #[test]
+    fn parse_write_multiple_accepts_valid_request() {
+        let pdu = [function_codes::WRITE_MULTIPLE_REGISTERS, 0x00, 0x00, 0x00, 0x02, 0x04, 0xAA, 0xBB, 0xCC, 0xDD];
+        match parse_modbus_request(&pdu).expect("should parse") {
+            ModbusRequest::WriteMultiple { start_addr, count, values } => {
+                assert_eq!(start_addr, 0);
+                assert_eq!(count, 2);
+                assert_eq!(values, &[0xAA, 0xBB, 0xCC, 0xDD]);
+            }
+            _ => panic!("wrong variant"),
+        }
+    }
+
+    #[test]
+    fn parse_rejects_unknown_function_code() {
+        let pdu = [0x99];
+        expect_err(parse_modbus_request(&pdu), exception_codes::ILLEGAL_FUNCTION);
+    }
+
+    #[test]
+    fn parse_rejects_empty_pdu() {
+        expect_err(parse_modbus_request(&[]), exception_codes::ILLEGAL_DATA_VALUE);
+    }
+
+    // ---- build_exception_response ----
+
+    #[test]
+    fn exception_response_echoes_transaction_and_sets_length_3() {
+        let mut out = [0u8; 9];
+        let len = build_exception_response(&mbap(0x1234, 6), function_codes::READ_HOLDING_REGISTERS, exception_codes::ILLEGAL_DATA_ADDRESS, &mut out).unwrap();
+        assert_eq!(len, 9);
+        assert_eq!(&out[0..2], &0x1234u16.to_be_bytes());
+        assert_eq!(&out[4..6], &3u16.to_be_bytes());
+        assert_eq!(out[7], function_codes::READ_HOLDING_REGISTERS | 0x80);
+        assert_eq!(out[8], exception_codes::ILLEGAL_DATA_ADDRESS);
+    }
+
+    #[test]
+    fn exception_response_rejects_undersized_buffer() {
+        let mut out = [0u8; 8];
+        assert!(build_exception_response(&mbap(0, 0), function_codes::READ_HOLDING_REGISTERS, exception_codes::ILLEGAL_FUNCTION, &mut out).is_err());
+    }
 }
@@ -0,0 +1,115 @@
+//! Microchip ENC424J600 SPI MAC+PHY driver
+//!
+//! Unlike the W5500, the ENC424J600 has no hardware TCP/IP offload - it
+//! only exchanges raw Ethernet frames with the host over its packet memory.
+//! This module is a reset/bring-up stub, not a working backend: it covers
+//! the register-level plumbing (reset, scratch-register readback, chip
+//! revision read) and nothing past that - there's no MAC address register
+//! write and no packet-memory TX/RX here yet. Turning this into a real
+//! transport needs both that raw-frame TX/RX and a software TCP/IP stack on
+//! top of it, which is exactly what the `smoltcp` MACRAW work elsewhere in
+//! this crate adds for the W5500; neither exists for this chip yet, so
+//! [`Enc424j600`]'s [`crate::backend::EthernetBackend`] impl has every
+//! per-socket method return `Err(())`.
+
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+/// Unbanked control register instructions (address + data, no bank select
+/// needed - the ENC424J600's "U" registers are addressed directly).
+const OPCODE_RCRU: u8 = 0x20; // Read Control Register Unbanked
+const OPCODE_WCRU: u8 = 0x22; // Write Control Register Unbanked
+const OPCODE_SETETHRST: u8 = 0xCA; // System reset
+
+/// EUDAST: scratch register read back after reset to confirm SPI is alive
+const REG_EUDAST: u16 = 0x016C;
+
+/// EIDLED: chip revision lives in the high byte
+const REG_EIDLED: u16 = 0x0138;
+
+/// Raw ENC424J600 SPI access - reset, identify, and read/write its
+/// unbanked control registers.
+pub struct Enc424j600<SPI: SpiDevice> {
+    spi: SPI,
+}
+
+impl<SPI: SpiDevice> Enc424j600<SPI> {
+    pub fn new(spi: SPI) -> Self {
+        Enc424j600 { spi }
+    }
+
+    /// Reset the chip and confirm SPI communication via the EUDAST scratch
+    /// register (write a pattern, read it back).
+    pub async fn init(&mut self) -> Result<(), ()> {
+        self.spi
+            .transaction(&mut [Operation::Write(&[OPCODE_SETETHRST])])
+            .await
+            .map_err(|_| ())?;
+        embassy_time::Timer::after_millis(1).await;
+
+        self.write_reg(REG_EUDAST, 0x1234).await?;
+        let readback = self.read_reg(REG_EUDAST).await?;
+        if readback != 0x1234 {
+            return Err(());
+        }
+
+        let id = self.read_reg(REG_EIDLED).await?;
+        defmt::info!("ENC424J600 EIDLED: 0x{:04X}", id);
+        Ok(())
+    }
+
+    /// Read a 16-bit unbanked control register.
+    async fn read_reg(&mut self, addr: u16) -> Result<u16, ()> {
+        let header = [OPCODE_RCRU, (addr & 0xFF) as u8, (addr >> 8) as u8];
+        let mut data = [0u8; 2];
+        self.spi
+            .transaction(&mut [Operation::Write(&header), Operation::Read(&mut data)])
+            .await
+            .map_err(|_| ())?;
+        Ok(u16::from_le_bytes(data))
+    }
+
+    /// Write a 16-bit unbanked control register.
+    async fn write_reg(&mut self, addr: u16, value: u16) -> Result<(), ()> {
+        let header = [OPCODE_WCRU, (addr & 0xFF) as u8, (addr >> 8) as u8];
+        let data = value.to_le_bytes();
+        self.spi
+            .transaction(&mut [Operation::Write(&header), Operation::Write(&data)])
+            .await
+            .map_err(|_| ())
+    }
+}
+
+/// [`crate::backend::EthernetBackend`] over a raw ENC424J600 MAC/PHY.
+///
+/// The ENC424J600 has no per-socket hardware state machine to query, and
+/// this driver doesn't yet implement raw frame TX/RX over its packet
+/// memory, let alone a software TCP/IP stack on top of that (see the
+/// MACRAW/smoltcp work elsewhere in this crate, which does both for the
+/// W5500). Every method here is a placeholder until that work lands for
+/// this chip. This still gives boards with an ENC424J600 fitted a named
+/// backend slot - swapping a real implementation in only needs this impl
+/// block filled in, not any change to `backend::service_socket` or the
+/// Modbus server logic.
+impl<SPI: SpiDevice> crate::backend::EthernetBackend for Enc424j600<SPI> {
+    async fn listen(&mut self, _socket: u8, _port: u16) -> Result<(), ()> {
+        // TODO: bring up a TCP listener once raw frame TX/RX over packet
+        // memory and a software TCP/IP stack both exist for this chip.
+        Err(())
+    }
+
+    async fn status(&mut self, _socket: u8) -> Result<crate::backend::SocketState, ()> {
+        Err(())
+    }
+
+    async fn recv(&mut self, _socket: u8, _buffer: &mut [u8]) -> Result<u16, ()> {
+        Err(())
+    }
+
+    async fn send(&mut self, _socket: u8, _data: &[u8]) -> Result<u16, ()> {
+        Err(())
+    }
+
+    async fn reopen(&mut self, _socket: u8, _port: u16) -> Result<(), ()> {
+        Err(())
+    }
+}
@@ -0,0 +1,226 @@
+//! On-chip flash persistence for board identity and the writable config block
+//!
+//! `modbus_2.rs` compiles `BOARD_ID`/`IP_ADDRESS`/`MAC_ADDRESS` in as consts,
+//! so re-labeling or re-IPing a board has meant a new firmware build. This
+//! module reads/writes a small CRC32-checked record in a reserved flash
+//! sector instead, holding the board identity plus the writable config
+//! block. Config values pushed over Modbus (see
+//! `common::handle_write_registers`'s `dirty` flag) survive a power cycle
+//! this way; the identity fields have no Modbus register, so a write over
+//! Modbus never changes them. An erased sector or a bad CRC (first
+//! boot, or a write that got interrupted) just falls back to the compiled
+//! defaults - there's no "corrupt flash" error path, only a cache miss.
+
+use defmt::{info, warn};
+use embassy_stm32::flash::{Blocking, Flash};
+use heapless::String;
+
+use crate::common::DeviceConfig;
+
+/// Network identity a board reads from flash instead of a compiled const.
+pub struct BoardIdentity {
+    pub board_id: String<16>,
+    pub ip_address: [u8; 4],
+    pub mac_address: [u8; 6],
+}
+
+/// Blocking flash handle, sized the same way `rtu::RtuUart` names its type.
+pub type BoardFlash = Flash<'static, Blocking>;
+
+/// Magic value guarding against a record written by a future, incompatible
+/// layout - a CRC can still match on garbage that merely happens to begin
+/// with the bytes this layout expects, but not on an all-0xFF erased sector.
+const MAGIC: u32 = 0x4D42_4346; // "MBCF"
+
+/// Reserved sector for the config record: Sector 7, the last sector of this
+/// board's 512 KB flash bank. STM32F401/F411 parts (this crate's SPI1/DMA2
+/// and USART2/DMA1 pin assignments are this family's Nucleo layout) use a
+/// non-uniform sector table - 4x16KB + 1x64KB + 3x128KB, not one flat erase
+/// granularity - so there's no single `ERASE_SIZE` constant to derive this
+/// from the way a uniform-page family would have. These bounds are taken
+/// directly from the reference manual's sector table (Sector 7: 128 KB at
+/// 0x0806_0000) rather than computed, and erasing exactly this range never
+/// touches Sector 6 or earlier, where the program image lives.
+const SECTOR_OFFSET: u32 = 0x0006_0000;
+const SECTOR_SIZE: u32 = 0x0002_0000; // 128 KB
+
+/// magic(4) + board_id(16) + ip(4) + mac(6) + heater_enable(1) +
+/// measurement_interval_s(2) + display_mode(2) + word_order(1) + crc32(4)
+const RECORD_LEN: usize = 4 + 16 + 4 + 6 + 1 + 2 + 2 + 1 + 4;
+
+/// Hand-rolled CRC32 (polynomial 0xEDB88320, reflected, init/final XOR
+/// 0xFFFFFFFF) - same bit-at-a-time style as `rtu::crc16_modbus`, just the
+/// wider polynomial the record's integrity check uses.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            if crc & 1 != 0 {
+                crc = (crc >> 1) ^ 0xEDB8_8320;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    !crc
+}
+
+fn encode(identity: &BoardIdentity, config: &DeviceConfig, out: &mut [u8; RECORD_LEN]) {
+    let mut pos = 0;
+    out[pos..pos + 4].copy_from_slice(&MAGIC.to_be_bytes());
+    pos += 4;
+
+    let id_bytes = identity.board_id.as_bytes();
+    out[pos..pos + 16].fill(0);
+    out[pos..pos + id_bytes.len().min(16)].copy_from_slice(&id_bytes[..id_bytes.len().min(16)]);
+    pos += 16;
+
+    out[pos..pos + 4].copy_from_slice(&identity.ip_address);
+    pos += 4;
+    out[pos..pos + 6].copy_from_slice(&identity.mac_address);
+    pos += 6;
+
+    out[pos] = config.heater_enable as u8;
+    pos += 1;
+    out[pos..pos + 2].copy_from_slice(&config.measurement_interval_s.to_be_bytes());
+    pos += 2;
+    out[pos..pos + 2].copy_from_slice(&config.display_mode.to_be_bytes());
+    pos += 2;
+    out[pos] = config.word_order as u8;
+    pos += 1;
+
+    let crc = crc32(&out[..pos]);
+    out[pos..pos + 4].copy_from_slice(&crc.to_be_bytes());
+}
+
+fn decode(record: &[u8; RECORD_LEN]) -> Option<(BoardIdentity, DeviceConfig)> {
+    let crc_pos = RECORD_LEN - 4;
+    let expected = u32::from_be_bytes(record[crc_pos..].try_into().ok()?);
+    if crc32(&record[..crc_pos]) != expected {
+        return None;
+    }
+
+    let mut pos = 0;
+    if u32::from_be_bytes(record[pos..pos + 4].try_into().ok()?) != MAGIC {
+        return None;
+    }
+    pos += 4;
+
+    let id_end = record[pos..pos + 16].iter().position(|&b| b == 0).map(|i| pos + i).unwrap_or(pos + 16);
+    let board_id = String::try_from(core::str::from_utf8(&record[pos..id_end]).ok()?).ok()?;
+    pos += 16;
+
+    let mut ip_address = [0u8; 4];
+    ip_address.copy_from_slice(&record[pos..pos + 4]);
+    pos += 4;
+
+    let mut mac_address = [0u8; 6];
+    mac_address.copy_from_slice(&record[pos..pos + 6]);
+    pos += 6;
+
+    let heater_enable = record[pos] != 0;
+    pos += 1;
+    let measurement_interval_s = u16::from_be_bytes([record[pos], record[pos + 1]]);
+    pos += 2;
+    let display_mode = u16::from_be_bytes([record[pos], record[pos + 1]]);
+    pos += 2;
+    let word_order = crate::common::WordOrder::from_register(record[pos] as u16)?;
+
+    Some((
+        BoardIdentity { board_id, ip_address, mac_address },
+        DeviceConfig { heater_enable, measurement_interval_s, display_mode, word_order, dirty: false },
+    ))
+}
+
+/// Read the config record out of the reserved sector, returning `None` on an
+/// erased sector or a CRC mismatch so the caller falls back to its compiled
+/// defaults.
+pub fn load(flash: &mut BoardFlash) -> Option<(BoardIdentity, DeviceConfig)> {
+    let mut record = [0u8; RECORD_LEN];
+    if flash.blocking_read(SECTOR_OFFSET, &mut record).is_err() {
+        warn!("flash_store: read failed, using compiled defaults");
+        return None;
+    }
+
+    match decode(&record) {
+        Some(loaded) => {
+            info!("flash_store: loaded board identity from flash");
+            Some(loaded)
+        }
+        None => {
+            info!("flash_store: no valid record (erased sector or CRC mismatch), using compiled defaults");
+            None
+        }
+    }
+}
+
+/// Erase and reprogram the reserved sector with `identity`/`config`.
+pub fn save(flash: &mut BoardFlash, identity: &BoardIdentity, config: &DeviceConfig) -> Result<(), ()> {
+    let mut record = [0u8; RECORD_LEN];
+    encode(identity, config, &mut record);
+
+    flash.blocking_erase(SECTOR_OFFSET, SECTOR_OFFSET + SECTOR_SIZE).map_err(|_| ())?;
+    flash.blocking_write(SECTOR_OFFSET, &record).map_err(|_| ())?;
+    info!("flash_store: saved board identity and config to flash");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::WordOrder;
+
+    fn sample() -> (BoardIdentity, DeviceConfig) {
+        (
+            BoardIdentity {
+                board_id: String::try_from("node-07").unwrap(),
+                ip_address: [192, 168, 1, 42],
+                mac_address: [0x02, 0x00, 0x00, 0x00, 0x00, 0x07],
+            },
+            DeviceConfig {
+                heater_enable: true,
+                measurement_interval_s: 30,
+                display_mode: 2,
+                word_order: WordOrder::CdAb,
+                dirty: false,
+            },
+        )
+    }
+
+    #[test]
+    fn crc32_matches_standard_test_vector() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn encode_decode_round_trips() {
+        let (identity, config) = sample();
+        let mut record = [0u8; RECORD_LEN];
+        encode(&identity, &config, &mut record);
+
+        let (loaded_identity, loaded_config) = decode(&record).expect("a freshly encoded record should decode");
+        assert_eq!(loaded_identity.board_id.as_str(), "node-07");
+        assert_eq!(loaded_identity.ip_address, [192, 168, 1, 42]);
+        assert_eq!(loaded_identity.mac_address, [0x02, 0x00, 0x00, 0x00, 0x00, 0x07]);
+        assert_eq!(loaded_config.heater_enable, true);
+        assert_eq!(loaded_config.measurement_interval_s, 30);
+        assert_eq!(loaded_config.display_mode, 2);
+        assert!(matches!(loaded_config.word_order, WordOrder::CdAb));
+    }
+
+    #[test]
+    fn decode_rejects_erased_sector() {
+        let erased = [0xFFu8; RECORD_LEN];
+        assert!(decode(&erased).is_none());
+    }
+
+    #[test]
+    fn decode_rejects_corrupted_record() {
+        let (identity, config) = sample();
+        let mut record = [0u8; RECORD_LEN];
+        encode(&identity, &config, &mut record);
+        record[10] ^= 0xFF; // flip a byte inside the board_id field
+        assert!(decode(&record).is_none());
+    }
+}
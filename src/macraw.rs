@@ -0,0 +1,302 @@
+//! Raw-frame (MACRAW) mode for the W5500, backed by `smoltcp`
+//!
+//! The offload mode in `common`/`backend` runs entirely on the W5500's own
+//! TCP state machine, so it's stuck with whatever that hardware offers: no
+//! control over retransmit timers or keep-alives, and eight sockets total
+//! shared across every transport. Putting socket 0 into MACRAW mode turns
+//! it into a plain Ethernet tap - raw frames in, raw frames out - and lets
+//! a full `smoltcp` interface run ARP, ICMP and TCP in software on top, at
+//! the cost of giving up that socket's hardware offload while this mode is
+//! active (sockets 1-7 are untouched and still usable the normal way).
+//!
+//! `smoltcp::phy::Device` is a synchronous, poll-driven interface, unlike
+//! the rest of this crate's `embedded-hal-async` SPI access, so [`W5500Raw`]
+//! takes a blocking `embedded_hal::spi::SpiDevice` rather than the async one
+//! `common::init_hardware` returns. Which mode a board runs is a
+//! compile-time choice - see the `macraw` feature gate in `modbus_2.rs` -
+//! since the two modes use the hardware completely differently and aren't
+//! meant to run side by side.
+
+use embedded_hal::spi::{Operation, SpiDevice as BlockingSpiDevice};
+use smoltcp::iface::{Config, Interface, SocketSet};
+use smoltcp::phy::{Device, DeviceCapabilities, Medium};
+use smoltcp::socket::tcp;
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
+
+use crate::chip::{Chip, W5500, W5500Address};
+use crate::common::{self, DeviceConfig, MbapHeader, SensorData};
+
+/// MACRAW is only meaningful on socket 0 - the W5500 dedicates that socket
+/// to tapping the whole link rather than terminating one TCP connection.
+const MACRAW_SOCKET: u8 = 0;
+const MAX_FRAME_LEN: usize = 1514;
+
+const SOCK_MR_MACRAW: u8 = 0x04;
+const SOCK_CMD_OPEN: u8 = 0x01;
+
+/// Raw Ethernet tap over W5500 socket 0.
+///
+/// Owns the same kind of SPI handle `common`'s offload helpers do, just a
+/// blocking one - `smoltcp::phy::Device::receive`/`transmit` aren't async,
+/// so there's no way to `.await` a transaction inside them.
+pub struct W5500Raw<SPI: BlockingSpiDevice> {
+    spi: SPI,
+}
+
+impl<SPI: BlockingSpiDevice> W5500Raw<SPI> {
+    /// Put socket 0 into MACRAW mode and open it.
+    ///
+    /// Unlike the offload path, nothing else touches this chip first - the
+    /// caller's RST pulse is the only other bring-up step - so this reads
+    /// back the version register itself to confirm the bus is actually
+    /// talking to a W5500 before doing anything else, the same sanity check
+    /// `common::init_hardware` does on the async bus. MACRAW's own RX filter
+    /// matches incoming unicast frames against the chip's SHAR register, so
+    /// `mac_addr` is programmed here too - without it the filter only ever
+    /// keeps broadcast/multicast traffic, not a real precondition the caller
+    /// can skip.
+    pub fn new(mut spi: SPI, mac_addr: [u8; 6]) -> Result<Self, ()> {
+        let mut version = [0u8; 1];
+        Self::reg_read(&mut spi, W5500::common_addr(W5500::COMMON_VERSION), &mut version)?;
+        if version[0] != W5500::VERSION {
+            return Err(());
+        }
+
+        Self::reg_write(&mut spi, W5500::common_addr(W5500::COMMON_SHAR0), &mac_addr)?;
+        Self::reg_write(&mut spi, W5500::socket_addr(MACRAW_SOCKET, W5500::SOCKET_MODE), &[SOCK_MR_MACRAW])?;
+        Self::reg_write(&mut spi, W5500::socket_addr(MACRAW_SOCKET, W5500::SOCKET_COMMAND), &[SOCK_CMD_OPEN])?;
+        Ok(W5500Raw { spi })
+    }
+
+    fn reg_write(spi: &mut SPI, addr: W5500Address, data: &[u8]) -> Result<(), ()> {
+        let header = [(addr.offset >> 8) as u8, (addr.offset & 0xFF) as u8, addr.control | 0x04];
+        spi.transaction(&mut [Operation::Write(&header), Operation::Write(data)]).map_err(|_| ())
+    }
+
+    fn reg_read(spi: &mut SPI, addr: W5500Address, buffer: &mut [u8]) -> Result<(), ()> {
+        let header = [(addr.offset >> 8) as u8, (addr.offset & 0xFF) as u8, addr.control & !0x04];
+        spi.transaction(&mut [Operation::Write(&header), Operation::Read(buffer)]).map_err(|_| ())
+    }
+
+    /// Pop the next received frame into `buffer`, if one is waiting.
+    ///
+    /// MACRAW mode prefixes every frame in the RX buffer with a 2-byte
+    /// length field (unlike the plain byte stream TCP/UDP sockets use), so
+    /// unlike `common::read_rx_data` this reads that length first.
+    fn recv_frame(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, ()> {
+        let mut size_bytes = [0u8; 2];
+        Self::reg_read(&mut self.spi, W5500::socket_addr(MACRAW_SOCKET, W5500::SOCKET_RX_RECEIVED_SIZE), &mut size_bytes)?;
+        if u16::from_be_bytes(size_bytes) == 0 {
+            return Ok(None);
+        }
+
+        let mut ptr_bytes = [0u8; 2];
+        Self::reg_read(&mut self.spi, W5500::socket_addr(MACRAW_SOCKET, W5500::SOCKET_RX_READ_PTR), &mut ptr_bytes)?;
+        let read_ptr = u16::from_be_bytes(ptr_bytes);
+
+        let mut len_bytes = [0u8; 2];
+        Self::reg_read(&mut self.spi, W5500::rx_addr(MACRAW_SOCKET, read_ptr), &mut len_bytes)?;
+        let frame_len = u16::from_be_bytes(len_bytes).saturating_sub(2) as usize;
+        if frame_len == 0 || frame_len > buffer.len() {
+            return Err(());
+        }
+
+        Self::reg_read(&mut self.spi, W5500::rx_addr(MACRAW_SOCKET, read_ptr.wrapping_add(2)), &mut buffer[..frame_len])?;
+
+        let new_ptr = read_ptr.wrapping_add(2).wrapping_add(frame_len as u16);
+        Self::reg_write(&mut self.spi, W5500::socket_addr(MACRAW_SOCKET, W5500::SOCKET_RX_READ_PTR), &new_ptr.to_be_bytes())?;
+        Self::reg_write(&mut self.spi, W5500::socket_addr(MACRAW_SOCKET, W5500::SOCKET_COMMAND), &[0x40])?; // RECV
+
+        Ok(Some(frame_len))
+    }
+
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), ()> {
+        let mut ptr_bytes = [0u8; 2];
+        Self::reg_read(&mut self.spi, W5500::socket_addr(MACRAW_SOCKET, W5500::SOCKET_TX_WRITE_PTR), &mut ptr_bytes)?;
+        let write_ptr = u16::from_be_bytes(ptr_bytes);
+
+        Self::reg_write(&mut self.spi, W5500::tx_addr(MACRAW_SOCKET, write_ptr), frame)?;
+
+        let new_ptr = write_ptr.wrapping_add(frame.len() as u16);
+        Self::reg_write(&mut self.spi, W5500::socket_addr(MACRAW_SOCKET, W5500::SOCKET_TX_WRITE_PTR), &new_ptr.to_be_bytes())?;
+        Self::reg_write(&mut self.spi, W5500::socket_addr(MACRAW_SOCKET, W5500::SOCKET_COMMAND), &[0x20]) // SEND
+    }
+}
+
+pub struct RawRxToken {
+    buffer: [u8; MAX_FRAME_LEN],
+    len: usize,
+}
+
+impl smoltcp::phy::RxToken for RawRxToken {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(mut self, f: F) -> R {
+        f(&mut self.buffer[..self.len])
+    }
+}
+
+pub struct RawTxToken<'a, SPI: BlockingSpiDevice> {
+    device: &'a mut W5500Raw<SPI>,
+}
+
+impl<'a, SPI: BlockingSpiDevice> smoltcp::phy::TxToken for RawTxToken<'a, SPI> {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        let result = f(&mut frame[..len]);
+        let _ = self.device.send_frame(&frame[..len]);
+        result
+    }
+}
+
+impl<SPI: BlockingSpiDevice> Device for W5500Raw<SPI> {
+    type RxToken<'a> = RawRxToken where Self: 'a;
+    type TxToken<'a> = RawTxToken<'a, SPI> where Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let mut buffer = [0u8; MAX_FRAME_LEN];
+        let len = self.recv_frame(&mut buffer).ok()??;
+        Some((RawRxToken { buffer, len }, RawTxToken { device: self }))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(RawTxToken { device: self })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = MAX_FRAME_LEN;
+        caps.medium = Medium::Ethernet;
+        caps
+    }
+}
+
+/// Run the software TCP/IP stack: Modbus on `modbus_port`, a tiny JSON
+/// status readout (via `serde-json-core`) on `diag_port`, both served off
+/// the same MACRAW tap.
+///
+/// This never returns - it owns the socket loop for as long as the board
+/// is in MACRAW mode.
+pub fn run_macraw_server<SPI: BlockingSpiDevice>(
+    mut device: W5500Raw<SPI>,
+    mac: [u8; 6],
+    ip: [u8; 4],
+    modbus_port: u16,
+    diag_port: u16,
+    sensor_data: &SensorData,
+    device_config: &mut DeviceConfig,
+) -> ! {
+    let hw_addr = HardwareAddress::Ethernet(EthernetAddress(mac));
+    let mut config = Config::new(hw_addr);
+    config.random_seed = 0;
+
+    let mut iface = Interface::new(config, &mut device, Instant::from_millis(0));
+    iface.update_ip_addrs(|addrs| {
+        addrs.push(IpCidr::new(IpAddress::v4(ip[0], ip[1], ip[2], ip[3]), 24)).ok();
+    });
+
+    // Plain locals, not `static mut` - this function never returns, so its
+    // stack frame (and everything borrowed from it, like `sockets_storage`
+    // below) lives for as long as the board is in MACRAW mode anyway.
+    let mut modbus_rx = [0u8; 512];
+    let mut modbus_tx = [0u8; 512];
+    let mut diag_rx = [0u8; 256];
+    let mut diag_tx = [0u8; 256];
+
+    let modbus_socket = tcp::Socket::new(tcp::SocketBuffer::new(&mut modbus_rx[..]), tcp::SocketBuffer::new(&mut modbus_tx[..]));
+    let diag_socket = tcp::Socket::new(tcp::SocketBuffer::new(&mut diag_rx[..]), tcp::SocketBuffer::new(&mut diag_tx[..]));
+
+    let mut sockets_storage: [smoltcp::iface::SocketStorage; 2] = Default::default();
+    let mut sockets = SocketSet::new(&mut sockets_storage[..]);
+    let modbus_handle = sockets.add(modbus_socket);
+    let diag_handle = sockets.add(diag_socket);
+
+    let mut modbus_assembler = common::ModbusFrameAssembler::new();
+
+    let mut uptime_ms: i64 = 0;
+    loop {
+        let timestamp = Instant::from_millis(uptime_ms);
+        iface.poll(timestamp, &mut device, &mut sockets);
+
+        let modbus_sock = sockets.get_mut::<tcp::Socket>(modbus_handle);
+        if !modbus_sock.is_open() {
+            modbus_assembler.reset();
+            let _ = modbus_sock.listen(modbus_port);
+        } else if modbus_sock.can_recv() {
+            service_modbus(modbus_sock, &mut modbus_assembler, sensor_data, device_config);
+        }
+
+        let diag_sock = sockets.get_mut::<tcp::Socket>(diag_handle);
+        if !diag_sock.is_open() {
+            let _ = diag_sock.listen(diag_port);
+        } else if diag_sock.can_recv() {
+            service_diagnostics(diag_sock, sensor_data);
+        }
+
+        uptime_ms += 10;
+    }
+}
+
+/// Read off `sock`, feed it through `assembler`, and dispatch every
+/// complete Modbus TCP request that comes out the other end - a single
+/// `recv_slice` may hold less than a frame (a partial request straddling
+/// two polls) or more than one (pipelined requests), and `assembler`
+/// absorbs both cases so the request parsing below always sees exactly one
+/// full MBAP+PDU frame at a time. Reuses the same request parsing and
+/// register map the offload mode's `backend::service_socket` uses.
+fn service_modbus(sock: &mut tcp::Socket, assembler: &mut common::ModbusFrameAssembler, sensor_data: &SensorData, device_config: &mut DeviceConfig) {
+    let mut request = [0u8; 260];
+    let bytes_read = match sock.recv_slice(&mut request) {
+        Ok(n) => n,
+        Err(_) => return,
+    };
+
+    let mut chunk = &request[..bytes_read];
+    loop {
+        let data = match assembler.push(chunk) {
+            Some(data) => data,
+            None => break,
+        };
+        chunk = &[];
+
+        if data.len() < 7 {
+            continue;
+        }
+        let mbap = match MbapHeader::from_bytes(data) {
+            Ok(mbap) => mbap,
+            Err(_) => continue,
+        };
+
+        let mut response = [0u8; 260];
+        if let Some(len) = common::dispatch_modbus_request(&mbap, &data[7..], sensor_data, device_config, &mut response) {
+            let _ = sock.send_slice(&response[..len]);
+        }
+    }
+}
+
+/// Drain whatever the client sent (a GET line, in practice) and reply with
+/// a one-line JSON snapshot of the register map - a diagnostics view of
+/// the same data Modbus serves, for anything that'd rather speak JSON.
+fn service_diagnostics(sock: &mut tcp::Socket, sensor_data: &SensorData) {
+    let mut scratch = [0u8; 64];
+    let _ = sock.recv_slice(&mut scratch);
+
+    #[derive(serde::Serialize)]
+    struct Status {
+        temperature: f32,
+        humidity: f32,
+        status: u16,
+        uptime: u32,
+    }
+
+    let status = Status {
+        temperature: sensor_data.temperature,
+        humidity: sensor_data.humidity,
+        status: sensor_data.status,
+        uptime: sensor_data.uptime,
+    };
+
+    let mut json_buf = [0u8; 128];
+    if let Ok(len) = serde_json_core::to_slice(&status, &mut json_buf) {
+        let _ = sock.send_slice(&json_buf[..len]);
+    }
+}
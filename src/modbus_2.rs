@@ -1,12 +1,29 @@
-#![no_std]
-#![no_main]
+// `cfg_attr`'d rather than plain attributes so `cargo test --workspace` can
+// build this crate for the host and run the `#[cfg(test)]` unit tests in
+// `common`/`rtu`/`flash_store` - the firmware build (no `test` cfg) is
+// unaffected.
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
+mod backend;
+mod chip;
 mod common;
+mod enc424j600;
+mod flash_store;
+#[cfg(feature = "macraw")]
+mod macraw;
+#[cfg(feature = "netstack")]
+mod net;
+mod rtu;
+mod sensor;
 
+use backend::W5500Backend;
+use chip::W5500;
 use defmt::{info, warn};
 use embassy_executor::Spawner;
 use embassy_stm32 as _;  // Import to register time driver
 use heapless;
+use sensor::EnvSensor;
 use {defmt_rtt as _, panic_probe as _};
 
 // Board 2 Configuration
@@ -14,48 +31,109 @@ const BOARD_ID: &str = "MODBUS_2";
 const IP_ADDRESS: [u8; 4] = [10, 10, 10, 200];
 const MAC_ADDRESS: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x20]; // Locally administered MAC
 const MODBUS_PORT: u16 = 502;
+#[cfg(feature = "macraw")]
+const DIAG_PORT: u16 = 8080;
+const RTU_SLAVE_ID: u8 = 1;
+const RTU_BAUD_RATE: u32 = 19200;
 
+/// Ethernet chip fitted on this board. Swapping parts only requires
+/// changing this alias; `common`'s hardware helpers are generic over `Chip`.
+type ActiveChip = W5500;
+
+/// Offload mode: the W5500's own hardware TCP state machine services every
+/// socket (see `backend::W5500Backend`). This is the default; enable the
+/// `macraw` feature to run a hand-rolled `smoltcp` stack on socket 0 instead
+/// (see `macraw::run_macraw_server`), which trades the other 7 sockets'
+/// offload for real retransmission/keep-alive control and a second,
+/// non-Modbus port on the same NIC; or enable `netstack` to run the same
+/// kind of software stack through the maintained `embassy-net-wiznet`
+/// driver and `embassy_net::Stack` instead (see `net::run_modbus_server`).
+#[cfg(not(any(feature = "macraw", feature = "netstack")))]
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     info!("========================================");
     info!("Week 9: Modbus TCP Slave - {}", BOARD_ID);
     info!("========================================");
 
+    // `init_hardware` used to call this itself; it's pulled out here so flash
+    // can be read before the network comes up (see `flash_store`).
+    let p = embassy_stm32::init(Default::default());
+    let mut board_flash = embassy_stm32::flash::Flash::new_blocking(p.FLASH);
+
+    // Board identity and the config block come from flash when a valid
+    // record is there, falling back to the compiled consts otherwise. Modbus
+    // writes reach only the config block (see
+    // `common::handle_write_registers`'s `dirty` flag); the identity fields
+    // have no Modbus register and are saved back unchanged.
+    let (board_id, ip_address, mac_address, mut device_config) = match flash_store::load(&mut board_flash) {
+        Some((identity, config)) => (identity.board_id, identity.ip_address, identity.mac_address, config),
+        None => (
+            heapless::String::try_from(BOARD_ID).unwrap(),
+            IP_ADDRESS,
+            MAC_ADDRESS,
+            common::DeviceConfig::default(),
+        ),
+    };
+    let board_identity = flash_store::BoardIdentity { board_id, ip_address, mac_address };
+
     // Initialize hardware (W5500 with network config)
-    let (mut spi, mut cs) = common::init_hardware(BOARD_ID, IP_ADDRESS, MAC_ADDRESS).await;
+    let spi = common::init_hardware::<ActiveChip>(&board_identity.board_id, board_identity.ip_address, board_identity.mac_address).await;
+    let mut backend = W5500Backend::<ActiveChip, _>::new(spi);
 
-    // Initialize SHT3x sensor
-    let mut sht3x = common::init_sht3x().await;
+    // Bring up the I2C1 bus and detect whichever sensor is populated on it
+    let i2c = common::init_i2c1().await;
+    let mut env_sensor = sensor::probe(i2c).await.expect("No supported environmental sensor detected on I2C1");
+    env_sensor.init().await.ok();
 
     // Initialize OLED display
     let mut oled = common::init_oled().await;
 
+    // Initialize RS-485 UART for Modbus RTU (second transport, same register map)
+    let mut rtu_uart = rtu::init_rtu(RTU_BAUD_RATE).await;
+
     // Display startup banner
-    common::display_startup(&mut oled, BOARD_ID, IP_ADDRESS);
+    common::display_startup(&mut oled, &board_identity.board_id, board_identity.ip_address);
 
     info!("=== Board ready - Network configured ===");
 
     // Create sensor data structure (will be updated with real readings)
     let mut sensor_data = common::SensorData::default();
+    // The sensor's soft reset in `init` leaves any heater off, so a heater
+    // setting restored from flash is applied on the first loop tick
+    let mut heater_applied = false;
+
+    // One frame assembler per hardware socket, so a frame split across
+    // `recv` calls on one socket survives to the next tick without being
+    // confused with another socket's in-flight frame
+    let mut assemblers: [common::ModbusFrameAssembler; common::NUM_SOCKETS as usize] =
+        core::array::from_fn(|_| common::ModbusFrameAssembler::new());
 
     // Take initial sensor reading
     info!("Taking initial sensor reading...");
-    match common::read_sht3x(&mut sht3x).await {
-        Ok((temp, hum)) => {
-            sensor_data.temperature = temp;
-            sensor_data.humidity = hum;
+    match env_sensor.read().await {
+        Ok(m) => {
+            sensor_data.temperature = m.temperature;
+            sensor_data.humidity = m.humidity;
+            if let Some(p) = m.pressure {
+                sensor_data.pressure = p;
+            }
+            if let Some(g) = m.gas {
+                sensor_data.gas_resistance = g;
+            }
+            sensor_data.status = common::status::OK;
             info!("Initial readings:");
-            info!("  Temperature: {} C (x10)", (temp * 10.0) as i32);
-            info!("  Humidity: {} % (x10)", (hum * 10.0) as i32);
+            info!("  Temperature: {} C (x10)", (m.temperature * 10.0) as i32);
+            info!("  Humidity: {} % (x10)", (m.humidity * 10.0) as i32);
         }
         Err(_) => {
             warn!("Failed to read sensor - using default values");
+            sensor_data.status = common::status::SENSOR_ERROR;
         }
     }
 
     // Monitor socket status and incoming data
     let mut loop_count = 0u32;
-    let mut is_connected = false;
+    let mut any_connected = false;
 
     loop {
         embassy_time::Timer::after_millis(500).await;
@@ -64,180 +142,192 @@ async fn main(spawner: Spawner) {
         sensor_data.uptime = sensor_data.uptime.wrapping_add(1);
         loop_count = loop_count.wrapping_add(1);
 
-        // Read sensor every 2 seconds (every 4 iterations of 500ms)
-        if loop_count % 4 == 0 {
-            match common::read_sht3x(&mut sht3x).await {
-                Ok((temp, hum)) => {
-                    sensor_data.temperature = temp;
-                    sensor_data.humidity = hum;
+        // Apply a remotely-written heater command as soon as it changes,
+        // rather than polling it on the sensor-read cadence below
+        if device_config.heater_enable != heater_applied {
+            if env_sensor.set_heater(device_config.heater_enable).await.is_ok() {
+                heater_applied = device_config.heater_enable;
+            }
+        }
+        sensor_data.status = if heater_applied {
+            sensor_data.status | common::status::HEATER_ON
+        } else {
+            sensor_data.status & !common::status::HEATER_ON
+        };
+
+        // Read the sensor on the cadence set by MEASUREMENT_INTERVAL_REGISTER
+        // (40101), in 500ms ticks, defaulting to every 2 seconds
+        let interval_ticks = (device_config.measurement_interval_s as u32 * 2).max(1);
+        if loop_count % interval_ticks == 0 {
+            match env_sensor.read().await {
+                Ok(m) => {
+                    sensor_data.temperature = m.temperature;
+                    sensor_data.humidity = m.humidity;
+                    if let Some(p) = m.pressure {
+                        sensor_data.pressure = p;
+                    }
+                    if let Some(g) = m.gas {
+                        sensor_data.gas_resistance = g;
+                    }
+                    sensor_data.status = (sensor_data.status & common::status::HEATER_ON) | common::status::OK;
                 }
                 Err(_) => {
-                    // Sensor read failed - keep previous values
+                    // Sensor read failed (I2C error or CRC mismatch) - keep
+                    // previous readings but flag the fault in register 40001
+                    sensor_data.status = (sensor_data.status & common::status::HEATER_ON) | common::status::SENSOR_ERROR;
                 }
             }
         }
 
         // Update OLED display every 2 seconds (every 4 iterations of 500ms)
         if loop_count % 4 == 0 {
-            common::update_display(&mut oled, &sensor_data, BOARD_ID, IP_ADDRESS, is_connected);
+            common::update_display(&mut oled, &sensor_data, &board_identity.board_id, board_identity.ip_address, any_connected, device_config.display_mode);
         }
 
-        // Check socket status
-        let status = match common::check_socket_status(&mut spi, &mut cs).await {
-            Ok(s) => s,
-            Err(_) => {
-                warn!("Failed to read socket status");
-                continue;
-            }
-        };
+        // Service every hardware socket independently so several Modbus
+        // masters (e.g. a historian and an operator panel) can each hold
+        // their own connection at once - service_socket gives each one its
+        // own scratch buffers and echoes that socket's own MBAP transaction
+        // ID back, so pipelined requests on different sockets never cross.
+        any_connected = false;
+        for socket in 0..common::NUM_SOCKETS {
+            let connected = backend::service_socket(
+                &mut backend,
+                socket,
+                MODBUS_PORT,
+                &sensor_data,
+                &mut device_config,
+                &mut assemblers[socket as usize],
+            )
+            .await;
+            any_connected |= connected;
+        }
 
-        // Handle socket state transitions
-        match status {
-            0x00 => {  // CLOSED - need to reopen and listen
-                warn!("Socket CLOSED - reopening...");
-                is_connected = false;
-                if let Err(_) = common::reopen_socket(&mut spi, &mut cs).await {
-                    warn!("Failed to reopen socket");
-                }
-                continue;
-            }
-            0x1C => {  // CLOSE_WAIT - client closed, we need to close too
-                info!("Socket CLOSE_WAIT - closing connection");
-                is_connected = false;
-                if let Err(_) = common::close_socket(&mut spi, &mut cs).await {
-                    warn!("Failed to close socket");
-                }
-                continue;
-            }
-            0x13 => {  // INIT - need to send LISTEN command
-                info!("Socket INIT - sending LISTEN");
-                is_connected = false;
-                if let Err(_) = common::listen_socket(&mut spi, &mut cs).await {
-                    warn!("Failed to send LISTEN command");
-                }
-                continue;
-            }
-            0x14 => {  // LISTEN - waiting for connection (normal state)
-                is_connected = false;
-                // Nothing to do, just wait
+        // Service the RS-485 Modbus RTU transport alongside the TCP sockets
+        rtu::service_rtu(&mut rtu_uart, RTU_SLAVE_ID, &sensor_data, &mut device_config).await;
+
+        // A write touching the config block (see `common::handle_write_registers`)
+        // sets `dirty` rather than hitting flash on every request - flush it
+        // here, off the request path, on the same 2-second cadence as the
+        // display update.
+        if device_config.dirty && loop_count % 4 == 0 {
+            match flash_store::save(&mut board_flash, &board_identity, &device_config) {
+                Ok(()) => device_config.dirty = false,
+                Err(()) => warn!("Failed to commit device config to flash"),
             }
-            0x17 => {  // ESTABLISHED - connection active
-                is_connected = true;
-                // Handle data below
+        }
+    }
+}
+
+/// MACRAW mode: socket 0 runs a software `smoltcp` stack instead of the
+/// hardware offload. `macraw::W5500Raw` needs a blocking `SpiDevice` -
+/// `embassy_stm32::spi::Spi` implements that alongside the async trait
+/// `init_hardware` uses, so this builds its own `ExclusiveDevice` around
+/// the same bus rather than reusing the async one the offload path hands
+/// back. Sensor init is the same auto-detecting `sensor::probe` the offload
+/// path uses - only the network side differs.
+#[cfg(feature = "macraw")]
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    use embassy_stm32::{
+        gpio::{Level, Output, Speed},
+        spi::{Config as SpiConfig, Spi},
+        time::Hertz,
+    };
+    use embedded_hal_bus::spi::ExclusiveDevice as BlockingExclusiveDevice;
+
+    info!("========================================");
+    info!("Week 9: Modbus TCP Slave - {} (MACRAW)", BOARD_ID);
+    info!("========================================");
+
+    let p = embassy_stm32::init(Default::default());
+
+    let mut spi_config = SpiConfig::default();
+    spi_config.frequency = Hertz(10_000_000);
+    let spi = Spi::new_blocking(p.SPI1, p.PA5, p.PA7, p.PA6, spi_config);
+    let cs_pin = Output::new(p.PB6, Level::High, Speed::VeryHigh);
+    let spi_device = BlockingExclusiveDevice::new_no_delay(spi, cs_pin).expect("Failed to create blocking SpiDevice");
+
+    // This mode never goes through `common::init_hardware`, so it has to
+    // pulse RST (PC7) itself - same reset sequence, just on the blocking bus
+    // this mode owns instead of the async one.
+    let mut rst_pin = Output::new(p.PC7, Level::High, Speed::VeryHigh);
+    rst_pin.set_low();
+    embassy_time::Timer::after_millis(100).await;
+    rst_pin.set_high();
+    embassy_time::Timer::after_millis(200).await;
+
+    let raw = macraw::W5500Raw::new(spi_device, MAC_ADDRESS).expect("Failed to bring socket 0 up in MACRAW mode");
+
+    let i2c = common::init_i2c1().await;
+    let mut env_sensor = sensor::probe(i2c).await.expect("No supported environmental sensor detected on I2C1");
+    env_sensor.init().await.ok();
+
+    let mut sensor_data = common::SensorData::default();
+    match env_sensor.read().await {
+        Ok(m) => {
+            sensor_data.temperature = m.temperature;
+            sensor_data.humidity = m.humidity;
+            if let Some(p) = m.pressure {
+                sensor_data.pressure = p;
             }
-            _ => {
-                // Unknown or transitional state
+            if let Some(g) = m.gas {
+                sensor_data.gas_resistance = g;
             }
+            sensor_data.status = common::status::OK;
         }
+        Err(_) => sensor_data.status = common::status::SENSOR_ERROR,
+    }
 
-        // Check for incoming data when connected
-        if status == 0x17 {  // ESTABLISHED
-            match common::check_rx_size(&mut spi, &mut cs).await {
-                Ok(rx_bytes) if rx_bytes > 0 => {
-                    info!("Connection ESTABLISHED - {} bytes available!", rx_bytes);
-
-                    // Read the data into a buffer
-                    let mut buffer = [0u8; 260]; // Max Modbus TCP frame
-                    match common::read_rx_data(&mut spi, &mut cs, &mut buffer).await {
-                        Ok(bytes_read) => {
-                            info!("Read {} bytes from RX buffer", bytes_read);
-
-                            let data = &buffer[..bytes_read as usize];
-                            info!("Received data (hex): {:02X}", data);
-
-                            // Try to parse as Modbus TCP
-                            if bytes_read >= 7 {
-                                match common::MbapHeader::from_bytes(data) {
-                                    Ok(mbap) => {
-                                        info!("MBAP Header parsed:");
-                                        info!("  Transaction ID: 0x{:04X}", mbap.transaction_id);
-                                        info!("  Protocol ID: 0x{:04X}", mbap.protocol_id);
-                                        info!("  Length: {}", mbap.length);
-                                        info!("  Unit ID: 0x{:02X}", mbap.unit_id);
-
-                                        // Parse PDU (after MBAP header)
-                                        if bytes_read >= 12 {  // MBAP (7) + FC (1) + Addr (2) + Count (2)
-                                            let pdu = &data[7..];
-                                            match common::parse_modbus_request(pdu) {
-                                                Ok((fc, addr, count)) => {
-                                                    info!("Modbus Request:");
-                                                    info!("  Function Code: 0x{:02X}", fc);
-                                                    info!("  Start Address: {}", addr);
-                                                    info!("  Register Count: {}", count);
-
-                                                    // Build Modbus response
-                                                    let mut response = [0u8; 260];
-                                                    let mut pos = 0;
-
-                                                    // Write MBAP header (copy from request)
-                                                    if let Ok(_) = mbap.to_bytes(&mut response[pos..pos+7]) {
-                                                        pos += 7;
-
-                                                        // Update length field for response
-                                                        // Length = unit_id (1) + fc (1) + byte_count (1) + data (count * 2)
-                                                        let response_length = 1 + 1 + 1 + (count * 2);
-                                                        response[4..6].copy_from_slice(&response_length.to_be_bytes());
-
-                                                        // Write PDU header
-                                                        response[pos] = fc;  // Function code
-                                                        pos += 1;
-                                                        response[pos] = (count * 2) as u8;  // Byte count
-                                                        pos += 1;
-
-                                                        // Use register handler to fill data from sensor readings
-                                                        match common::handle_read_registers(
-                                                            addr,
-                                                            count,
-                                                            &sensor_data,
-                                                            &mut response[pos..]
-                                                        ) {
-                                                            Ok(data_len) => {
-                                                                pos += data_len;
-                                                                info!("Sending {} byte response", pos);
-
-                                                                // Send response
-                                                                match common::write_tx_data(&mut spi, &mut cs, &response[..pos]).await {
-                                                                    Ok(bytes_sent) => {
-                                                                        info!("Response sent: {} bytes", bytes_sent);
-                                                                    }
-                                                                    Err(_) => {
-                                                                        info!("Failed to send response");
-                                                                    }
-                                                                }
-                                                            }
-                                                            Err(exception_code) => {
-                                                                info!("Register read error - exception: 0x{:02X}", exception_code);
-                                                                // TODO: Send Modbus exception response
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                                Err(exception) => {
-                                                    info!("Modbus parse error - exception: 0x{:02X}", exception);
-                                                }
-                                            }
-                                        }
-                                    }
-                                    Err(_) => {
-                                        info!("Failed to parse MBAP header");
-                                    }
-                                }
-                            } else {
-                                info!("Frame too small for Modbus TCP (< 7 bytes)");
-                            }
-                        }
-                        Err(_) => {
-                            info!("Failed to read RX data");
-                        }
-                    }
-                }
-                Ok(_) => {
-                    // Connected but no data yet
-                }
-                Err(_) => {
-                    info!("Failed to read RX size");
-                }
+    // Writes to the heater-enable register (40100) are accepted into
+    // `device_config`, but MACRAW mode's socket loop below is synchronous
+    // (smoltcp's `Device` trait has no `.await`), so it can't drive the
+    // async I2C heater command the offload mode's main loop does - a real
+    // heater toggle here needs its own async task signalled by this config.
+    let mut device_config = common::DeviceConfig::default();
+
+    info!("=== Board ready - MACRAW stack serving Modbus:{} diagnostics:{} ===", MODBUS_PORT, DIAG_PORT);
+    macraw::run_macraw_server(raw, MAC_ADDRESS, IP_ADDRESS, MODBUS_PORT, DIAG_PORT, &sensor_data, &mut device_config);
+}
+
+/// Netstack mode: the W5500 runs MACRAW under `embassy-net-wiznet`, with a
+/// real `embassy_net::Stack` (not our own hand-rolled `smoltcp` glue, see
+/// `macraw`) owning retransmission and connection state. Sensor/OLED/RTU
+/// init are unchanged - only the network side differs.
+#[cfg(feature = "netstack")]
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    info!("========================================");
+    info!("Week 9: Modbus TCP Slave - {} (netstack)", BOARD_ID);
+    info!("========================================");
+
+    let stack = net::init_netstack(spawner, MAC_ADDRESS, IP_ADDRESS).await;
+
+    let i2c = common::init_i2c1().await;
+    let mut env_sensor = sensor::probe(i2c).await.expect("No supported environmental sensor detected on I2C1");
+    env_sensor.init().await.ok();
+
+    let mut sensor_data = common::SensorData::default();
+    match env_sensor.read().await {
+        Ok(m) => {
+            sensor_data.temperature = m.temperature;
+            sensor_data.humidity = m.humidity;
+            if let Some(p) = m.pressure {
+                sensor_data.pressure = p;
             }
+            if let Some(g) = m.gas {
+                sensor_data.gas_resistance = g;
+            }
+            sensor_data.status = common::status::OK;
         }
+        Err(_) => sensor_data.status = common::status::SENSOR_ERROR,
     }
+
+    // As with MACRAW mode, a real heater toggle here would need its own
+    // task signalled by this config rather than being driven from this
+    // one-shot read - see the equivalent note on the MACRAW main above.
+    let mut device_config = common::DeviceConfig::default();
+
+    info!("=== Board ready - netstack serving Modbus:{} ===", MODBUS_PORT);
+    net::run_modbus_server(&stack, MODBUS_PORT, &sensor_data, &mut device_config).await;
 }
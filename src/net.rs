@@ -0,0 +1,176 @@
+//! Software TCP/IP stack for the W5500 via `embassy-net-wiznet`
+//!
+//! `backend`/`W5500Backend` drives the W5500's own hardware TCP offload
+//! engine by hand: `common::check_socket_status`/`listen_socket`/
+//! `open_socket`/`check_rx_size`/`read_rx_data`/`write_tx_data` poll raw
+//! socket-status bytes (`0x00`/`0x13`/`0x14`/`0x17`/`0x1C`) and reimplement
+//! the CLOSED -> INIT -> LISTEN -> ESTABLISHED -> CLOSE_WAIT transitions
+//! around them. That gets eight independent hardware sockets for free, but
+//! every transition is this crate's own responsibility, and retransmission/
+//! window management are whatever the offload engine happens to do.
+//!
+//! This module instead dedicates one socket to MACRAW mode and hands it to
+//! the upstream `embassy-net-wiznet` driver underneath a real
+//! `embassy_net::Stack`, so TCP correctness - retransmits, windowing, the
+//! LISTEN/ESTABLISHED/CLOSE_WAIT lifecycle - is smoltcp's problem, not
+//! ours; the Modbus loop just `accept()`s a `TcpSocket` and `read()`s/
+//! `write()`s it.
+//!
+//! This is a different stack from `macraw`'s hand-rolled `smoltcp::phy::Device`
+//! impl - that module wires its own blocking MACRAW glue straight to
+//! `smoltcp::iface::Interface`/`SocketSet`; this one hands the same kind of
+//! MACRAW socket to the maintained, async, interrupt-driven
+//! `embassy-net-wiznet` crate instead, and serves through `embassy_net`'s
+//! `Stack`/`TcpSocket` rather than talking to `smoltcp` sockets directly. A
+//! board picks one network stack per build (see the `netstack` feature in
+//! `modbus_2.rs`); this one currently serves one connection at a time - see
+//! `mapfumo/wk9-opcua-modbus#chunk2-2` for concurrent masters on a stack
+//! like this one.
+
+use embassy_executor::Spawner;
+use embassy_net::tcp::TcpSocket;
+use embassy_net::{Config as NetConfig, Ipv4Address, Ipv4Cidr, Stack, StackResources, StaticConfigV4};
+use embassy_net_wiznet::chip::W5500 as WiznetW5500;
+use embassy_net_wiznet::{Device, Runner, State};
+use embassy_stm32::exti::ExtiInput;
+use embassy_stm32::gpio::{Level, Output, Pull, Speed};
+use embassy_stm32::peripherals;
+use embassy_stm32::spi::{Config as SpiConfig, Spi};
+use embassy_stm32::time::Hertz;
+use embassy_time::Delay;
+use embedded_hal_bus::spi::ExclusiveDevice;
+use static_cell::StaticCell;
+
+use crate::common::{self, DeviceConfig, MbapHeader, SensorData};
+
+/// This mode's own SPI handle type - a second, independent `ExclusiveDevice`
+/// around SPI1 rather than the one `common::init_hardware` returns, since
+/// `embassy_net_wiznet::new` takes ownership of the bus for the lifetime of
+/// the stack and drives the chip's reset/MAC setup itself.
+type W5500SpiDevice = ExclusiveDevice<
+    Spi<'static, peripherals::SPI1, peripherals::DMA2_CH3, peripherals::DMA2_CH2>,
+    Output<'static, peripherals::PB6>,
+    Delay,
+>;
+
+/// W5500 interrupt line - new wiring this mode needs that the hardware-offload
+/// and MACRAW-`smoltcp` modes don't: `embassy-net-wiznet` is edge-triggered
+/// off `/INT` rather than polling status registers on a timer.
+type W5500IntPin = ExtiInput<'static, peripherals::PC8>;
+
+type W5500Runner = Runner<'static, WiznetW5500, W5500SpiDevice, W5500IntPin, Output<'static, peripherals::PC7>>;
+
+static STATE: StaticCell<State<8, 8>> = StaticCell::new();
+static RESOURCES: StaticCell<StackResources<4>> = StaticCell::new();
+
+/// Background task driving the W5500 MACRAW link - must keep running for the
+/// whole lifetime of the `Stack` it feeds.
+#[embassy_executor::task]
+async fn eth_task(mut runner: W5500Runner) -> ! {
+    runner.run().await
+}
+
+/// Background task pumping `embassy_net`'s own poll loop.
+#[embassy_executor::task]
+async fn net_task(mut runner: embassy_net::Runner<'static, Device<'static>>) -> ! {
+    runner.run().await
+}
+
+/// Bring up the W5500 in MACRAW mode under `embassy-net-wiznet` and spawn
+/// the background tasks that drive it, returning the `Stack` handle the
+/// Modbus server accepts connections on.
+///
+/// Pins: SCK=PA5, MISO=PA6, MOSI=PA7, CS=PB6, RST=PC7, INT=PC8 - the same
+/// SPI1/CS/RST assignment `common::init_hardware` uses, plus the INT line
+/// this driver needs.
+pub async fn init_netstack(spawner: Spawner, mac_addr: [u8; 6], ip_addr: [u8; 4]) -> Stack<'static> {
+    let p = embassy_stm32::init(Default::default());
+
+    let mut spi_config = SpiConfig::default();
+    spi_config.frequency = Hertz(10_000_000);
+    let spi = Spi::new(p.SPI1, p.PA5, p.PA7, p.PA6, p.DMA2_CH3, p.DMA2_CH2, spi_config);
+    let cs_pin = Output::new(p.PB6, Level::High, Speed::VeryHigh);
+    let spi_device = ExclusiveDevice::new(spi, cs_pin, Delay).expect("Failed to create W5500 SpiDevice");
+
+    let int_pin = ExtiInput::new(p.PC8, p.EXTI8, Pull::Up);
+    let rst_pin = Output::new(p.PC7, Level::High, Speed::VeryHigh);
+
+    let state = STATE.init(State::<8, 8>::new());
+    let (device, runner) = embassy_net_wiznet::new(mac_addr, state, spi_device, int_pin, rst_pin)
+        .await
+        .expect("Failed to initialize W5500 in MACRAW mode");
+    spawner.spawn(eth_task(runner)).expect("Failed to spawn W5500 link task");
+
+    let net_config = NetConfig::ipv4_static(StaticConfigV4 {
+        address: Ipv4Cidr::new(Ipv4Address::new(ip_addr[0], ip_addr[1], ip_addr[2], ip_addr[3]), 24),
+        gateway: None,
+        dns_servers: heapless::Vec::new(),
+    });
+    let resources = RESOURCES.init(StackResources::<4>::new());
+    // The seed only needs to differ across boots to pick unpredictable local
+    // ports/ISNs; this board has no RNG peripheral wired up, so a fixed
+    // value is the honest choice rather than faking entropy.
+    let (stack, runner) = embassy_net::new(device, net_config, resources, 0x5500_5500_5500_5500);
+    spawner.spawn(net_task(runner)).expect("Failed to spawn net stack task");
+
+    stack
+}
+
+/// Serve Modbus TCP on `port` forever, one connection at a time.
+///
+/// Mirrors `macraw::service_modbus`'s request handling, just driven by
+/// `embassy_net::tcp::TcpSocket`'s async `accept`/`read`/`write` instead of
+/// `smoltcp::socket::tcp::Socket`'s synchronous buffer access.
+pub async fn run_modbus_server(
+    stack: &Stack<'static>,
+    port: u16,
+    sensor_data: &SensorData,
+    device_config: &mut DeviceConfig,
+) -> ! {
+    let mut rx_buffer = [0u8; 260];
+    let mut tx_buffer = [0u8; 260];
+
+    loop {
+        let mut socket = TcpSocket::new(*stack, &mut rx_buffer, &mut tx_buffer);
+        if socket.accept(port).await.is_err() {
+            continue;
+        }
+
+        // One assembler per connection - TCP segmentation never needs to be
+        // stitched across a reconnect, so a fresh instance here is enough.
+        let mut assembler = common::ModbusFrameAssembler::new();
+
+        loop {
+            let mut request = [0u8; 260];
+            let bytes_read = match socket.read(&mut request).await {
+                Ok(0) => break, // peer closed
+                Ok(n) => n,
+                Err(_) => break,
+            };
+
+            let mut chunk = &request[..bytes_read];
+            loop {
+                let data = match assembler.push(chunk) {
+                    Some(data) => data,
+                    None => break,
+                };
+                chunk = &[];
+
+                if data.len() < 7 {
+                    continue;
+                }
+                let mbap = match MbapHeader::from_bytes(data) {
+                    Ok(mbap) => mbap,
+                    Err(_) => continue,
+                };
+
+                let mut response = [0u8; 260];
+                if let Some(len) = common::dispatch_modbus_request(&mbap, &data[7..], sensor_data, device_config, &mut response) {
+                    let _ = socket.write(&response[..len]).await;
+                }
+            }
+        }
+
+        socket.close();
+    }
+}
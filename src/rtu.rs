@@ -0,0 +1,295 @@
+//! Modbus RTU transport over UART (RS-485)
+//!
+//! Alongside the W5500-based Modbus TCP server in `common`, this module lets
+//! the node speak Modbus RTU on a second transport so both can serve the
+//! same `registers`/`SensorData` map. RTU has no length prefix, so frames
+//! are delimited purely by a silent interval on the line (idle-line
+//! detection) rather than a byte count, and every frame carries a trailing
+//! CRC-16 instead of relying on TCP for integrity.
+
+use defmt::{info, warn};
+use embassy_stm32::{
+    bind_interrupts,
+    peripherals,
+    usart::{Config as UsartConfig, InterruptHandler, Uart},
+};
+
+use crate::common::{self, function_codes, DeviceConfig, ModbusRequest, SensorData};
+
+bind_interrupts!(struct Usart2Irqs {
+    USART2 => InterruptHandler<peripherals::USART2>;
+});
+
+/// Modbus RTU UART handle (USART2, DMA-backed)
+pub type RtuUart = Uart<'static, peripherals::USART2, peripherals::DMA1_CH7, peripherals::DMA1_CH5>;
+
+/// Maximum Modbus RTU frame size (slave addr + PDU + 2-byte CRC)
+pub const MAX_FRAME_LEN: usize = 256;
+
+/// Initialize the RS-485 UART for Modbus RTU
+///
+/// Pins: PA2 (TX), PA3 (RX) on USART2
+///
+/// # Arguments
+/// * `baud` - Line baud rate; used to size the idle-line timeout
+pub async fn init_rtu(baud: u32) -> RtuUart {
+    info!("Initializing Modbus RTU UART (USART2) at {} baud", baud);
+
+    let p = unsafe { embassy_stm32::Peripherals::steal() };
+
+    let mut config = UsartConfig::default();
+    config.baudrate = baud;
+
+    Uart::new(
+        p.USART2,
+        p.PA3, // RX
+        p.PA2, // TX
+        Usart2Irqs,
+        p.DMA1_CH7, // TX DMA
+        p.DMA1_CH5, // RX DMA
+        config,
+    )
+    .expect("Failed to initialize Modbus RTU UART")
+}
+
+/// Read one Modbus RTU frame, delimited by idle-line detection
+///
+/// `read_until_idle` returns as soon as the USART's idle-line detection
+/// fires, which happens after one character-time of silence - shorter than
+/// the 3.5 character-time gap Modbus RTU uses between frames. A master that
+/// sends one frame with no inter-character gaps yields one frame per call
+/// with no length prefix required; a pause of one character-time or more
+/// inside a frame splits it across two calls.
+pub async fn read_frame(uart: &mut RtuUart, buffer: &mut [u8]) -> Result<usize, ()> {
+    uart.read_until_idle(buffer).await.map_err(|_| ())
+}
+
+/// Compute the Modbus CRC-16 (polynomial 0xA001, reflected, init 0xFFFF)
+pub fn crc16_modbus(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            if crc & 0x0001 != 0 {
+                crc = (crc >> 1) ^ 0xA001;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    crc
+}
+
+/// Validate a received frame's trailing CRC-16 (low byte first)
+///
+/// Returns the frame with the CRC stripped off on success.
+fn validate_crc(frame: &[u8]) -> Result<&[u8], ()> {
+    if frame.len() < 4 {
+        // Minimum: addr + function + 2 CRC bytes
+        return Err(());
+    }
+    let (body, crc_bytes) = frame.split_at(frame.len() - 2);
+    let expected = crc16_modbus(body);
+    let received = u16::from_le_bytes([crc_bytes[0], crc_bytes[1]]);
+    if expected != received {
+        return Err(());
+    }
+    Ok(body)
+}
+
+/// Append a CRC-16 (low byte first) to `buffer[..len]`, returning the new length
+fn append_crc(buffer: &mut [u8], len: usize) -> usize {
+    let crc = crc16_modbus(&buffer[..len]);
+    let crc_bytes = crc.to_le_bytes();
+    buffer[len] = crc_bytes[0];
+    buffer[len + 1] = crc_bytes[1];
+    len + 2
+}
+
+/// Build an RTU exception response into `buffer`, returning its length
+///
+/// RTU has no MBAP header to echo, so the frame is just `slave_id` +
+/// `function_code | 0x80` + the exception byte, CRC'd the same way as any
+/// other RTU response - see `common::build_exception_response` for the TCP
+/// equivalent.
+fn build_exception_response(buffer: &mut [u8], slave_id: u8, function_code: u8, exception_code: u8) -> usize {
+    buffer[0] = slave_id;
+    buffer[1] = function_code | 0x80;
+    buffer[2] = exception_code;
+    append_crc(buffer, 3)
+}
+
+/// Read, validate and dispatch one Modbus RTU request
+///
+/// Polls for a frame with a short timeout so this can be interleaved with
+/// TCP socket servicing in the same round-robin main loop rather than
+/// blocking it until a master happens to transmit. Frames addressed to
+/// another slave are silently ignored (no response). Frames that fail
+/// their CRC are dropped without a response, per the Modbus RTU
+/// specification.
+pub async fn service_rtu(uart: &mut RtuUart, slave_id: u8, sensor_data: &SensorData, device_config: &mut DeviceConfig) {
+    let mut rx_buffer = [0u8; MAX_FRAME_LEN];
+    let bytes_read = match embassy_time::with_timeout(
+        embassy_time::Duration::from_millis(5),
+        read_frame(uart, &mut rx_buffer),
+    )
+    .await
+    {
+        Ok(Ok(n)) => n,
+        Ok(Err(_)) => {
+            warn!("RTU: UART read error");
+            return;
+        }
+        Err(_) => return, // no frame within this tick - try again next loop
+    };
+
+    let frame = match validate_crc(&rx_buffer[..bytes_read]) {
+        Ok(frame) => frame,
+        Err(_) => {
+            // Bad CRC - drop the frame without responding
+            return;
+        }
+    };
+
+    let addr = frame[0];
+    if addr != slave_id {
+        // Not addressed to us - ignore
+        return;
+    }
+
+    let pdu = &frame[1..];
+    let mut tx_buffer = [0u8; MAX_FRAME_LEN];
+    tx_buffer[0] = slave_id;
+
+    match common::parse_modbus_request(pdu) {
+        Ok(ModbusRequest::Read { function_code, start_addr, count }) => {
+            let mut pos = 1;
+            tx_buffer[pos] = function_code;
+            pos += 1;
+            tx_buffer[pos] = (count * 2) as u8;
+            pos += 1;
+
+            let read_result = if function_code == function_codes::READ_INPUT_REGISTERS {
+                common::handle_read_input_registers(start_addr, count, sensor_data, device_config.word_order, &mut tx_buffer[pos..])
+            } else {
+                common::handle_read_registers(start_addr, count, sensor_data, device_config.word_order, &mut tx_buffer[pos..])
+            };
+            match read_result {
+                Ok(data_len) => {
+                    pos += data_len;
+                    let len = append_crc(&mut tx_buffer, pos);
+                    if uart.write(&tx_buffer[..len]).await.is_err() {
+                        warn!("RTU: failed to send response");
+                    }
+                }
+                Err(exception_code) => {
+                    info!("RTU: register read error - exception: 0x{:02X}", exception_code);
+                    let len = build_exception_response(&mut tx_buffer, slave_id, function_code, exception_code);
+                    if uart.write(&tx_buffer[..len]).await.is_err() {
+                        warn!("RTU: failed to send response");
+                    }
+                }
+            }
+        }
+        Ok(ModbusRequest::WriteSingle { addr, value }) => {
+            let values = value.to_be_bytes();
+            match common::handle_write_registers(addr, 1, &values, device_config) {
+                Ok(()) => {
+                    // FC06 echoes the request's address and value verbatim
+                    let mut pos = 1;
+                    tx_buffer[pos] = function_codes::WRITE_SINGLE_REGISTER;
+                    pos += 1;
+                    tx_buffer[pos..pos + 2].copy_from_slice(&addr.to_be_bytes());
+                    pos += 2;
+                    tx_buffer[pos..pos + 2].copy_from_slice(&value.to_be_bytes());
+                    pos += 2;
+                    let len = append_crc(&mut tx_buffer, pos);
+                    if uart.write(&tx_buffer[..len]).await.is_err() {
+                        warn!("RTU: failed to send response");
+                    }
+                }
+                Err(exception_code) => {
+                    info!("RTU: register write error - exception: 0x{:02X}", exception_code);
+                    let len =
+                        build_exception_response(&mut tx_buffer, slave_id, function_codes::WRITE_SINGLE_REGISTER, exception_code);
+                    if uart.write(&tx_buffer[..len]).await.is_err() {
+                        warn!("RTU: failed to send response");
+                    }
+                }
+            }
+        }
+        Ok(ModbusRequest::WriteMultiple { start_addr, count, values }) => {
+            match common::handle_write_registers(start_addr, count, values, device_config) {
+                Ok(()) => {
+                    // FC16 echoes the request's start address and quantity
+                    let mut pos = 1;
+                    tx_buffer[pos] = function_codes::WRITE_MULTIPLE_REGISTERS;
+                    pos += 1;
+                    tx_buffer[pos..pos + 2].copy_from_slice(&start_addr.to_be_bytes());
+                    pos += 2;
+                    tx_buffer[pos..pos + 2].copy_from_slice(&count.to_be_bytes());
+                    pos += 2;
+                    let len = append_crc(&mut tx_buffer, pos);
+                    if uart.write(&tx_buffer[..len]).await.is_err() {
+                        warn!("RTU: failed to send response");
+                    }
+                }
+                Err(exception_code) => {
+                    info!("RTU: register write error - exception: 0x{:02X}", exception_code);
+                    let len = build_exception_response(
+                        &mut tx_buffer,
+                        slave_id,
+                        function_codes::WRITE_MULTIPLE_REGISTERS,
+                        exception_code,
+                    );
+                    if uart.write(&tx_buffer[..len]).await.is_err() {
+                        warn!("RTU: failed to send response");
+                    }
+                }
+            }
+        }
+        Err(exception_code) => {
+            info!("RTU: parse error - exception: 0x{:02X}", exception_code);
+            let function_code = pdu.first().copied().unwrap_or(0);
+            let len = build_exception_response(&mut tx_buffer, slave_id, function_code, exception_code);
+            if uart.write(&tx_buffer[..len]).await.is_err() {
+                warn!("RTU: failed to send response");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_matches_known_vectors() {
+        // Classic Modbus CRC examples (e.g. Modbus_over_serial_line_V1_02.pdf
+        // worked examples): CRC is transmitted low byte first.
+        assert_eq!(crc16_modbus(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]), 0xCDC5);
+        assert_eq!(crc16_modbus(&[0x01, 0x04, 0x02, 0xFF, 0xFF]), 0x80B8);
+    }
+
+    #[test]
+    fn validate_crc_accepts_matching_frame_and_strips_it() {
+        let mut frame = [0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0, 0];
+        let len = append_crc(&mut frame, 6);
+        assert_eq!(len, 8);
+        let body = validate_crc(&frame[..len]).expect("crc should validate");
+        assert_eq!(body, &[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]);
+    }
+
+    #[test]
+    fn validate_crc_rejects_corrupted_byte() {
+        let mut frame = [0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0, 0];
+        let len = append_crc(&mut frame, 6);
+        frame[0] ^= 0xFF; // corrupt the slave address after the CRC was computed
+        assert!(validate_crc(&frame[..len]).is_err());
+    }
+
+    #[test]
+    fn validate_crc_rejects_frame_too_short_for_addr_function_and_crc() {
+        assert!(validate_crc(&[0x01, 0x03, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,818 @@
+//! Generic environmental sensor support, with I2C auto-detection
+//!
+//! Earlier revisions of this firmware talked directly to a hard-wired
+//! SHT31-D. Boards get populated with whatever temperature/humidity part is
+//! on hand, so [`EnvSensor`] abstracts over the wire protocol the same way
+//! [`crate::backend::EthernetBackend`] abstracts over the network
+//! controller, and [`probe`] figures out which part is actually attached at
+//! boot instead of assuming one.
+
+use embassy_time::Timer;
+use embedded_hal_async::i2c::I2c;
+
+/// One reading from any supported sensor. `pressure`/`gas` are `None` on
+/// the temperature/humidity-only parts (SHT3x, HTU21D/Si7021, AM2320); the
+/// BME680 always fills `pressure`, and fills `gas` when its gas heater is
+/// enabled and reported stable for that conversion.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Measurement {
+    pub temperature: f32,
+    pub humidity: f32,
+    pub pressure: Option<f32>,
+    pub gas: Option<f32>,
+}
+
+/// A temperature/humidity (and optionally pressure/gas) sensor on an I2C
+/// bus. `init` brings the part up from power-on/reset; `read` takes one
+/// measurement.
+pub trait EnvSensor {
+    async fn init(&mut self) -> Result<(), ()>;
+    async fn read(&mut self) -> Result<Measurement, ()>;
+}
+
+/// Sensirion CRC-8 (polynomial 0x31, MSB-first) with a caller-supplied init
+/// value - SHT3x uses 0xFF, HTU21D/Si7021 use 0x00.
+fn crc8(bytes: &[u8], init: u8) -> u8 {
+    let mut crc = init;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            if crc & 0x80 != 0 {
+                crc = (crc << 1) ^ 0x31;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+/// Modbus-style CRC-16 (polynomial 0xA001, reflected, init 0xFFFF), used by
+/// the AM2320's response framing.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            if crc & 0x0001 != 0 {
+                crc = (crc >> 1) ^ 0xA001;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    crc
+}
+
+// ---------------------------------------------------------------------
+// SHT3x (Sensirion SHT30/31/32-D, address 0x44 with ADDR low, 0x45 high)
+// ---------------------------------------------------------------------
+
+/// Sensirion SHT3x over I2C.
+pub struct Sht3x<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+/// Validate and convert a 6-byte SHT3x sample (temp MSB/LSB/CRC, humidity
+/// MSB/LSB/CRC) into `(temperature_c, humidity_pct)`. Pulled out of the
+/// `Sht3x` impl block since it's pure data handling with no I2C dependency
+/// - same reasoning as `crc8`/`crc16` living at module level above.
+fn sht3x_decode(data: &[u8; 6]) -> Result<(f32, f32), ()> {
+    if crc8(&data[0..2], 0xFF) != data[2] || crc8(&data[3..5], 0xFF) != data[5] {
+        return Err(());
+    }
+    let temp_raw = u16::from_be_bytes([data[0], data[1]]);
+    let hum_raw = u16::from_be_bytes([data[3], data[4]]);
+    Ok((-45.0 + 175.0 * (temp_raw as f32 / 65535.0), 100.0 * (hum_raw as f32 / 65535.0)))
+}
+
+impl<I2C: I2c> Sht3x<I2C> {
+    /// Enable or disable the on-chip heater (command 0x306D/0x3066) - useful
+    /// for de-condensing the sensor in humid deployments, at the cost of
+    /// warm/dry readings while it's running.
+    pub async fn set_heater(&mut self, on: bool) -> Result<(), ()> {
+        let cmd = if on { [0x30, 0x6D] } else { [0x30, 0x66] };
+        self.i2c.write(self.address, &cmd).await.map_err(|_| ())
+    }
+
+    /// Start periodic measurement mode (1 measurement/sec, high
+    /// repeatability - command 0x2236). Once started, `read_periodic` pulls
+    /// the latest reading with FETCH_DATA instead of re-issuing a one-shot
+    /// command (and its 20ms wait) every cycle.
+    pub async fn start_periodic(&mut self) -> Result<(), ()> {
+        self.i2c.write(self.address, &[0x22, 0x36]).await.map_err(|_| ())
+    }
+
+    /// Fetch the latest reading from periodic measurement mode (FETCH_DATA,
+    /// command 0xE000). Must be called after `start_periodic`.
+    pub async fn read_periodic(&mut self) -> Result<Measurement, ()> {
+        self.i2c.write(self.address, &[0xE0, 0x00]).await.map_err(|_| ())?;
+        let mut data = [0u8; 6];
+        self.i2c.read(self.address, &mut data).await.map_err(|_| ())?;
+        let (temperature, humidity) = sht3x_decode(&data)?;
+        Ok(Measurement { temperature, humidity, pressure: None, gas: None })
+    }
+}
+
+impl<I2C: I2c> EnvSensor for Sht3x<I2C> {
+    async fn init(&mut self) -> Result<(), ()> {
+        self.i2c.write(self.address, &[0x30, 0xA2]).await.map_err(|_| ())?; // Soft reset
+        Timer::after_millis(2).await;
+        // Run in periodic mode from here on - `read` below just FETCH_DATAs
+        // the latest sample instead of re-triggering a one-shot conversion
+        // every cycle. Wait out one conversion so the first `read` after
+        // this doesn't race the chip's first internal sample.
+        self.start_periodic().await?;
+        Timer::after_millis(20).await;
+        Ok(())
+    }
+
+    async fn read(&mut self) -> Result<Measurement, ()> {
+        self.read_periodic().await
+    }
+}
+
+/// Soft reset + status-register CRC check, used by [`probe`] to confirm an
+/// SHT3x actually sits at `address` rather than just ACKing the bus.
+async fn probe_sht3x<I2C: I2c>(i2c: &mut I2C, address: u8) -> Result<(), ()> {
+    i2c.write(address, &[0x30, 0xA2]).await.map_err(|_| ())?;
+    Timer::after_millis(2).await;
+    i2c.write(address, &[0xF3, 0x2D]).await.map_err(|_| ())?; // Read status register
+    let mut status = [0u8; 3];
+    i2c.read(address, &mut status).await.map_err(|_| ())?;
+    if crc8(&status[0..2], 0xFF) != status[2] {
+        return Err(());
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------
+// HTU21D / Si7021 (address 0x40, pin- and command-compatible)
+// ---------------------------------------------------------------------
+
+const HTU21D_ADDRESS: u8 = 0x40;
+
+/// Measurement Specialties HTU21D / Silicon Labs Si7021.
+pub struct Htu21d<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C: I2c> Htu21d<I2C> {
+    fn check_crc(word: &[u8; 2], crc: u8) -> Result<(), ()> {
+        if crc8(word, 0x00) != crc {
+            return Err(());
+        }
+        Ok(())
+    }
+}
+
+impl<I2C: I2c> EnvSensor for Htu21d<I2C> {
+    async fn init(&mut self) -> Result<(), ()> {
+        self.i2c.write(HTU21D_ADDRESS, &[0xFE]).await.map_err(|_| ())?; // Soft reset
+        Timer::after_millis(15).await;
+        Ok(())
+    }
+
+    async fn read(&mut self) -> Result<Measurement, ()> {
+        // Trigger temperature, no hold master (0xF3)
+        self.i2c.write(HTU21D_ADDRESS, &[0xF3]).await.map_err(|_| ())?;
+        Timer::after_millis(50).await;
+        let mut temp_data = [0u8; 3];
+        self.i2c.read(HTU21D_ADDRESS, &mut temp_data).await.map_err(|_| ())?;
+        Self::check_crc(&[temp_data[0], temp_data[1]], temp_data[2])?;
+
+        // Trigger humidity, no hold master (0xF5)
+        self.i2c.write(HTU21D_ADDRESS, &[0xF5]).await.map_err(|_| ())?;
+        Timer::after_millis(50).await;
+        let mut hum_data = [0u8; 3];
+        self.i2c.read(HTU21D_ADDRESS, &mut hum_data).await.map_err(|_| ())?;
+        Self::check_crc(&[hum_data[0], hum_data[1]], hum_data[2])?;
+
+        // Bottom 2 status bits aren't part of the measurement value
+        let temp_raw = u16::from_be_bytes([temp_data[0], temp_data[1]]) & !0x0003;
+        let hum_raw = u16::from_be_bytes([hum_data[0], hum_data[1]]) & !0x0003;
+        let temperature = -46.85 + 175.72 * (temp_raw as f32 / 65536.0);
+        let humidity = -6.0 + 125.0 * (hum_raw as f32 / 65536.0);
+        Ok(Measurement { temperature, humidity, pressure: None, gas: None })
+    }
+}
+
+/// Read back the user register as an identity/presence check - there's no
+/// CRC on it, so this just confirms something HTU21D/Si7021-shaped answers
+/// at 0x40.
+async fn probe_htu21d<I2C: I2c>(i2c: &mut I2C) -> Result<(), ()> {
+    i2c.write(HTU21D_ADDRESS, &[0xE7]).await.map_err(|_| ())?;
+    let mut user_reg = [0u8; 1];
+    i2c.read(HTU21D_ADDRESS, &mut user_reg).await.map_err(|_| ())
+}
+
+// ---------------------------------------------------------------------
+// AM2320 (Aosong, address 0x5C, sleeps between accesses)
+// ---------------------------------------------------------------------
+
+const AM2320_ADDRESS: u8 = 0x5C;
+
+/// Aosong AM2320. Unlike the other parts, it sleeps between accesses
+/// and must be woken with a dummy write - expected to NAK - before every
+/// command.
+pub struct Am2320<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C: I2c> Am2320<I2C> {
+    async fn wake(&mut self) {
+        let _ = self.i2c.write(AM2320_ADDRESS, &[]).await;
+        Timer::after_micros(850).await;
+    }
+
+    /// Read humidity+temperature registers (function 0x03, start 0x00,
+    /// 4 registers) and CRC-check the response.
+    async fn read_registers(&mut self) -> Result<[u8; 4], ()> {
+        self.i2c.write(AM2320_ADDRESS, &[0x03, 0x00, 0x04]).await.map_err(|_| ())?;
+        Timer::after_millis(2).await;
+
+        let mut data = [0u8; 8]; // function + len + 4 data bytes + 2 CRC bytes
+        self.i2c.read(AM2320_ADDRESS, &mut data).await.map_err(|_| ())?;
+
+        let (body, crc_bytes) = data.split_at(6);
+        let expected = crc16(body);
+        let received = u16::from_le_bytes([crc_bytes[0], crc_bytes[1]]);
+        if expected != received {
+            return Err(());
+        }
+        Ok([data[2], data[3], data[4], data[5]])
+    }
+}
+
+impl<I2C: I2c> EnvSensor for Am2320<I2C> {
+    async fn init(&mut self) -> Result<(), ()> {
+        self.wake().await;
+        Ok(())
+    }
+
+    async fn read(&mut self) -> Result<Measurement, ()> {
+        self.wake().await;
+        let data = self.read_registers().await?;
+
+        let hum_raw = u16::from_be_bytes([data[0], data[1]]);
+        let temp_raw = u16::from_be_bytes([data[2], data[3]]);
+        // Top bit of the temperature word is a sign flag, not magnitude
+        let temperature = if temp_raw & 0x8000 != 0 {
+            -((temp_raw & 0x7FFF) as f32) / 10.0
+        } else {
+            temp_raw as f32 / 10.0
+        };
+        let humidity = hum_raw as f32 / 10.0;
+
+        Ok(Measurement { temperature, humidity, pressure: None, gas: None })
+    }
+}
+
+/// Wake + read-registers, used by both the real driver and [`probe`] -
+/// taking `&mut I2C` here (rather than an owned [`Am2320`]) lets `probe` try
+/// this before deciding the bus belongs to this part.
+async fn probe_am2320<I2C: I2c>(i2c: &mut I2C) -> Result<(), ()> {
+    let _ = i2c.write(AM2320_ADDRESS, &[]).await; // Wake - expected to NAK
+    Timer::after_micros(850).await;
+
+    i2c.write(AM2320_ADDRESS, &[0x03, 0x00, 0x04]).await.map_err(|_| ())?;
+    Timer::after_millis(2).await;
+    let mut data = [0u8; 8];
+    i2c.read(AM2320_ADDRESS, &mut data).await.map_err(|_| ())?;
+    let (body, crc_bytes) = data.split_at(6);
+    if crc16(body) != u16::from_le_bytes([crc_bytes[0], crc_bytes[1]]) {
+        return Err(());
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------
+// BME680 (Bosch temperature/humidity/pressure/gas, address 0x76/0x77)
+// ---------------------------------------------------------------------
+
+/// Oversampling for one BME680 measurement channel - higher values trade
+/// conversion time for less noise. `Skip` disables that channel entirely.
+#[derive(Clone, Copy)]
+pub enum Oversampling {
+    Skip,
+    X1,
+    X2,
+    X4,
+    X8,
+    X16,
+}
+
+impl Oversampling {
+    fn bits(self) -> u8 {
+        match self {
+            Oversampling::Skip => 0b000,
+            Oversampling::X1 => 0b001,
+            Oversampling::X2 => 0b010,
+            Oversampling::X4 => 0b011,
+            Oversampling::X8 => 0b100,
+            Oversampling::X16 => 0b101,
+        }
+    }
+}
+
+/// IIR filter coefficient applied to temperature/pressure - one of
+/// 0/1/3/7/15/31/63/127 per the datasheet's `filter[2:0]` field.
+#[derive(Clone, Copy)]
+pub enum IirFilter {
+    Off,
+    Coeff1,
+    Coeff3,
+    Coeff7,
+    Coeff15,
+    Coeff31,
+    Coeff63,
+    Coeff127,
+}
+
+impl IirFilter {
+    fn bits(self) -> u8 {
+        match self {
+            IirFilter::Off => 0,
+            IirFilter::Coeff1 => 1,
+            IirFilter::Coeff3 => 2,
+            IirFilter::Coeff7 => 3,
+            IirFilter::Coeff15 => 4,
+            IirFilter::Coeff31 => 5,
+            IirFilter::Coeff63 => 6,
+            IirFilter::Coeff127 => 7,
+        }
+    }
+}
+
+/// Gas heater profile: target hot-plate temperature and how long to hold it
+/// before a gas conversion is considered stable.
+#[derive(Clone, Copy)]
+pub struct GasHeaterProfile {
+    pub target_temp_c: u16,
+    pub duration_ms: u16,
+}
+
+/// Builder for the BME680's oversampling/filter/heater configuration,
+/// written to its control registers by [`Bme680`]'s `init`.
+pub struct Bme680Config {
+    temperature_oversampling: Oversampling,
+    humidity_oversampling: Oversampling,
+    pressure_oversampling: Oversampling,
+    iir_filter: IirFilter,
+    gas_heater: Option<GasHeaterProfile>,
+}
+
+impl Default for Bme680Config {
+    fn default() -> Self {
+        Bme680Config {
+            temperature_oversampling: Oversampling::X2,
+            humidity_oversampling: Oversampling::X1,
+            pressure_oversampling: Oversampling::X4,
+            iir_filter: IirFilter::Coeff3,
+            gas_heater: Some(GasHeaterProfile { target_temp_c: 320, duration_ms: 150 }),
+        }
+    }
+}
+
+impl Bme680Config {
+    pub fn temperature_oversampling(mut self, os: Oversampling) -> Self {
+        self.temperature_oversampling = os;
+        self
+    }
+
+    pub fn humidity_oversampling(mut self, os: Oversampling) -> Self {
+        self.humidity_oversampling = os;
+        self
+    }
+
+    pub fn pressure_oversampling(mut self, os: Oversampling) -> Self {
+        self.pressure_oversampling = os;
+        self
+    }
+
+    pub fn iir_filter(mut self, filter: IirFilter) -> Self {
+        self.iir_filter = filter;
+        self
+    }
+
+    /// `None` disables the gas heater (and run-gas bit) entirely.
+    pub fn gas_heater(mut self, profile: Option<GasHeaterProfile>) -> Self {
+        self.gas_heater = profile;
+        self
+    }
+}
+
+const BME680_REG_STATUS: u8 = 0x1D; // meas_status_0: new_data/gas_measuring/heat_stab/gas_valid
+const BME680_REG_DATA0: u8 = 0x1F; // press_msb.. through gas_r_lsb
+const BME680_REG_CTRL_GAS1: u8 = 0x71;
+const BME680_REG_CTRL_HUM: u8 = 0x72;
+const BME680_REG_CTRL_MEAS: u8 = 0x74;
+const BME680_REG_CONFIG: u8 = 0x75;
+const BME680_REG_RES_HEAT0: u8 = 0x5A;
+const BME680_REG_GAS_WAIT0: u8 = 0x64;
+const BME680_REG_CHIP_ID: u8 = 0xD0;
+const BME680_REG_RESET: u8 = 0xE0;
+const BME680_CHIP_ID: u8 = 0x61;
+
+// Per-chip trim/calibration registers (datasheet section 3.11, "trimming
+// parameter readout") - two contiguous blocks, plus the range-switching
+// error byte that lives alongside the regular data registers rather than
+// in either block.
+const BME680_REG_COEFF1: u8 = 0x89; // 25 bytes, through 0xA1
+const BME680_REG_COEFF2: u8 = 0xE1; // 16 bytes, through 0xF0
+const BME680_REG_RANGE_SWITCHING_ERROR: u8 = 0x04;
+
+/// Per-chip compensation trim read out of the BME680 once at `init` time.
+/// Bosch's datasheet formulas (and every driver that implements them -
+/// Bosch's own, Adafruit's, BSEC) require this block; there's no
+/// documented "skip calibration" mode; reporting pressure/gas without it
+/// is reporting noise shaped like a reading.
+#[derive(Clone, Copy, Default)]
+struct Bme680Calibration {
+    par_t1: u16,
+    par_t2: i16,
+    par_t3: i8,
+    par_p1: u16,
+    par_p2: i16,
+    par_p3: i8,
+    par_p4: i16,
+    par_p5: i16,
+    par_p6: i8,
+    par_p7: i8,
+    par_p8: i16,
+    par_p9: i16,
+    par_p10: u8,
+    par_h1: u16,
+    par_h2: u16,
+    par_h3: i8,
+    par_h4: i8,
+    par_h5: i8,
+    par_h6: u8,
+    par_h7: i8,
+    par_g1: i8,
+    par_g2: i16,
+    par_g3: i8,
+    range_sw_err: i8,
+}
+
+/// Bosch BME680 over I2C (address 0x76 with SDO pulled low, 0x77 high).
+pub struct Bme680<I2C> {
+    i2c: I2C,
+    address: u8,
+    config: Bme680Config,
+    calib: Bme680Calibration,
+}
+
+impl<I2C: I2c> Bme680<I2C> {
+    pub fn new(i2c: I2C, address: u8, config: Bme680Config) -> Self {
+        Bme680 { i2c, address, config, calib: Bme680Calibration::default() }
+    }
+
+    async fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), ()> {
+        self.i2c.write(self.address, &[reg, value]).await.map_err(|_| ())
+    }
+
+    async fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), ()> {
+        self.i2c.write_read(self.address, &[reg], buf).await.map_err(|_| ())
+    }
+
+    /// Read both trim blocks plus the range-switching-error byte and
+    /// decode them into [`Bme680Calibration`] per the datasheet's bit
+    /// layout - signed fields are two's complement, `par_h1`/`par_h2` are
+    /// 12-bit values packed across three shared bytes, `range_sw_err` is
+    /// a signed 4-bit nibble.
+    async fn read_calibration(&mut self) -> Result<(), ()> {
+        let mut c1 = [0u8; 25];
+        self.read_regs(BME680_REG_COEFF1, &mut c1).await?;
+        let mut c2 = [0u8; 16];
+        self.read_regs(BME680_REG_COEFF2, &mut c2).await?;
+        let mut sw_err = [0u8; 1];
+        self.read_regs(BME680_REG_RANGE_SWITCHING_ERROR, &mut sw_err).await?;
+
+        self.calib = Bme680Calibration {
+            par_t2: i16::from_le_bytes([c1[1], c1[2]]),
+            par_t3: c1[3] as i8,
+            par_p1: u16::from_le_bytes([c1[5], c1[6]]),
+            par_p2: i16::from_le_bytes([c1[7], c1[8]]),
+            par_p3: c1[9] as i8,
+            par_p4: i16::from_le_bytes([c1[11], c1[12]]),
+            par_p5: i16::from_le_bytes([c1[13], c1[14]]),
+            par_p7: c1[15] as i8,
+            par_p6: c1[16] as i8,
+            par_p8: i16::from_le_bytes([c1[19], c1[20]]),
+            par_p9: i16::from_le_bytes([c1[21], c1[22]]),
+            par_p10: c1[23],
+
+            par_h2: ((c2[0] as u16) << 4) | (c2[1] as u16 >> 4),
+            par_h1: ((c2[2] as u16) << 4) | (c2[1] as u16 & 0x0F),
+            par_h3: c2[3] as i8,
+            par_h4: c2[4] as i8,
+            par_h5: c2[5] as i8,
+            par_h6: c2[6],
+            par_h7: c2[7] as i8,
+            par_t1: u16::from_le_bytes([c2[8], c2[9]]),
+            par_g2: i16::from_le_bytes([c2[10], c2[11]]),
+            par_g1: c2[12] as i8,
+            par_g3: c2[13] as i8,
+
+            // Stored as a signed nibble (bits 7:4) - sign-extend it by
+            // reading the byte as i8 and arithmetic-shifting it down.
+            range_sw_err: (sw_err[0] as i8) >> 4,
+        };
+
+        Ok(())
+    }
+
+    /// Encode a target plate temperature into the chip's `res_heat_0`
+    /// register, using the datasheet's simplified (room-ambient, no
+    /// per-chip trim) approximation rather than the full calibration-data
+    /// formula - close enough to reach a stable heater setpoint.
+    fn heater_resistance_code(target_temp_c: u16) -> u8 {
+        let target = target_temp_c.min(400) as i32;
+        let amb_temp = 25i32; // assumed ambient; not read back from the chip
+        let var1 = amb_temp / 2 - 2;
+        let var2 = (target - amb_temp - var1) * 3;
+        let code = 128 + var2 / 5 + var1 * 2;
+        code.clamp(0, 255) as u8
+    }
+
+    /// Encode a heater duration into the chip's `gas_wait_0` register
+    /// (6-bit mantissa + 2-bit x4 multiplier, per the datasheet).
+    fn heater_duration_code(duration_ms: u16) -> u8 {
+        let mut factor = 0u8;
+        let mut duration = duration_ms;
+        while duration > 0x3F {
+            duration /= 4;
+            factor += 1;
+        }
+        (factor << 6) | (duration as u8 & 0x3F)
+    }
+
+    /// Compensated temperature in deg C, and `t_fine` - the intermediate
+    /// value pressure/humidity compensation both also need - per the
+    /// datasheet's floating-point compensation formula.
+    fn calc_temperature(calib: &Bme680Calibration, temp_adc: u32) -> (f32, f32) {
+        let var1 = ((temp_adc as f32 / 16384.0) - (calib.par_t1 as f32 / 1024.0)) * calib.par_t2 as f32;
+        let var2 = (((temp_adc as f32 / 131072.0) - (calib.par_t1 as f32 / 8192.0))
+            * ((temp_adc as f32 / 131072.0) - (calib.par_t1 as f32 / 8192.0)))
+            * (calib.par_t3 as f32 * 16.0);
+        let t_fine = var1 + var2;
+        (t_fine / 5120.0, t_fine)
+    }
+
+    /// Compensated pressure in hPa, per the datasheet's floating-point
+    /// compensation formula.
+    fn calc_pressure(calib: &Bme680Calibration, press_adc: u32, t_fine: f32) -> f32 {
+        let mut var1 = (t_fine / 2.0) - 64000.0;
+        let mut var2 = var1 * var1 * (calib.par_p6 as f32 / 131_072.0);
+        var2 += var1 * calib.par_p5 as f32 * 2.0;
+        var2 = (var2 / 4.0) + (calib.par_p4 as f32 * 65536.0);
+        var1 = (((calib.par_p3 as f32 * var1 * var1) / 16384.0) + (calib.par_p2 as f32 * var1)) / 524_288.0;
+        var1 = (1.0 + (var1 / 32768.0)) * calib.par_p1 as f32;
+
+        let mut pressure = 1_048_576.0 - press_adc as f32;
+        pressure = ((pressure - (var2 / 4096.0)) * 6250.0) / var1;
+        var1 = (calib.par_p9 as f32 * pressure * pressure) / 2_147_483_648.0;
+        var2 = pressure * (calib.par_p8 as f32 / 32768.0);
+        let var3 = (pressure / 256.0) * (pressure / 256.0) * (pressure / 256.0) * (calib.par_p10 as f32 / 131_072.0);
+        pressure += (var1 + var2 + var3 + (calib.par_p7 as f32 * 128.0)) / 16.0;
+
+        pressure / 100.0 // Pa -> hPa
+    }
+
+    /// Compensated relative humidity in %RH, per the datasheet's
+    /// floating-point compensation formula.
+    fn calc_humidity(calib: &Bme680Calibration, hum_adc: u32, temp_comp: f32) -> f32 {
+        let var1 = hum_adc as f32 - ((calib.par_h1 as f32 * 16.0) + ((calib.par_h3 as f32 / 2.0) * temp_comp));
+        let var2 = var1
+            * ((calib.par_h2 as f32 / 262_144.0)
+                * (1.0 + ((calib.par_h4 as f32 / 16384.0) * temp_comp) + ((calib.par_h5 as f32 / 1_048_576.0) * temp_comp * temp_comp)));
+        let var3 = calib.par_h6 as f32 / 16384.0;
+        let var4 = calib.par_h7 as f32 / 2_097_152.0;
+        (var2 + ((var3 + (var4 * temp_comp)) * var2 * var2)).clamp(0.0, 100.0)
+    }
+
+    /// Convert a raw gas ADC reading + range into a resistance in Ohms,
+    /// per the datasheet's floating-point compensation formula (uses the
+    /// per-range lookup constants from the datasheet's Table 15).
+    fn calc_gas_resistance(calib: &Bme680Calibration, gas_adc: u32, gas_range: u8) -> f32 {
+        const LOOKUP_K1: [f32; 16] = [0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, -0.8, 0.0, 0.0, -0.2, -0.5, 0.0, -1.0, 0.0, 0.0];
+        const LOOKUP_K2: [f32; 16] = [0.0, 0.0, 0.0, 0.0, 0.1, 0.7, 0.0, -0.8, -0.1, 0.0, 0.0, 0.0, 0.0, 0.1, 0.0, 0.0];
+
+        let range = gas_range as usize & 0x0F;
+        let var1 = 1340.0 + (5.0 * calib.range_sw_err as f32);
+        let var2 = var1 * (1.0 + (LOOKUP_K1[range] / 100.0));
+        let var3 = 1.0 + (LOOKUP_K2[range] / 100.0);
+
+        1.0 / (var3 * 0.000_000_125 * (1u32 << gas_range) as f32 * (((gas_adc as f32 - 512.0) / var2) + 1.0))
+    }
+}
+
+impl<I2C: I2c> EnvSensor for Bme680<I2C> {
+    async fn init(&mut self) -> Result<(), ()> {
+        self.write_reg(BME680_REG_RESET, 0xB6).await?; // Soft reset
+        Timer::after_millis(5).await;
+
+        self.read_calibration().await?;
+
+        let osrs_t = self.config.temperature_oversampling.bits();
+        let osrs_h = self.config.humidity_oversampling.bits();
+        let osrs_p = self.config.pressure_oversampling.bits();
+        let filter = self.config.iir_filter.bits();
+
+        self.write_reg(BME680_REG_CTRL_HUM, osrs_h).await?;
+        self.write_reg(BME680_REG_CONFIG, filter << 2).await?;
+        self.write_reg(BME680_REG_CTRL_MEAS, (osrs_t << 5) | (osrs_p << 2)).await?;
+
+        if let Some(profile) = self.config.gas_heater {
+            let heat_res = Self::heater_resistance_code(profile.target_temp_c);
+            let heat_dur = Self::heater_duration_code(profile.duration_ms);
+            self.write_reg(BME680_REG_RES_HEAT0, heat_res).await?;
+            self.write_reg(BME680_REG_GAS_WAIT0, heat_dur).await?;
+            self.write_reg(BME680_REG_CTRL_GAS1, 0x10).await?; // run_gas=1, heater profile 0
+        }
+
+        Ok(())
+    }
+
+    async fn read(&mut self) -> Result<Measurement, ()> {
+        let osrs_t = self.config.temperature_oversampling.bits();
+        let osrs_p = self.config.pressure_oversampling.bits();
+        let gas_enabled = self.config.gas_heater.is_some();
+
+        // Trigger one forced-mode conversion (mode = 0b01)
+        let ctrl_meas = (osrs_t << 5) | (osrs_p << 2) | 0b01;
+        self.write_reg(BME680_REG_CTRL_MEAS, ctrl_meas).await?;
+
+        // Poll meas_status_0 rather than wait a fixed delay - a stabilizing
+        // gas heater can take well over 100ms, which would either be wasted
+        // time on gas-disabled profiles or too short on slow ones.
+        let mut status = [0u8; 1];
+        let mut ready = false;
+        for _ in 0..50 {
+            self.read_regs(BME680_REG_STATUS, &mut status).await?;
+            if status[0] & 0x80 != 0 {
+                ready = true;
+                break;
+            }
+            Timer::after_millis(10).await;
+        }
+        if !ready {
+            return Err(());
+        }
+
+        let mut data = [0u8; 13]; // 0x1F..=0x2B: pressure(3) temp(3) humidity(2) reserved(3) gas(2)
+        self.read_regs(BME680_REG_DATA0, &mut data).await?;
+
+        let press_adc = ((data[0] as u32) << 12) | ((data[1] as u32) << 4) | (data[2] as u32 >> 4);
+        let temp_adc = ((data[3] as u32) << 12) | ((data[4] as u32) << 4) | (data[5] as u32 >> 4);
+        let hum_adc = ((data[6] as u32) << 8) | (data[7] as u32);
+
+        // Apply this chip's calibration trim (read in `init`) per the
+        // datasheet's compensation formulas - `t_fine` from temperature
+        // feeds both pressure and humidity compensation.
+        let (temperature, t_fine) = Self::calc_temperature(&self.calib, temp_adc);
+        let pressure = Self::calc_pressure(&self.calib, press_adc, t_fine);
+        let humidity = Self::calc_humidity(&self.calib, hum_adc, temperature);
+
+        let gas = if gas_enabled && status[0] & 0x10 != 0 {
+            // heat_stab_r set - the plate reached its target, so gas_r is valid
+            let gas_adc = ((data[11] as u32) << 2) | (data[12] as u32 >> 6);
+            let gas_range = data[12] & 0x0F;
+            Some(Self::calc_gas_resistance(&self.calib, gas_adc, gas_range))
+        } else {
+            None
+        };
+
+        Ok(Measurement { temperature, humidity, pressure: Some(pressure), gas })
+    }
+}
+
+async fn probe_bme680<I2C: I2c>(i2c: &mut I2C, address: u8) -> Result<(), ()> {
+    let mut chip_id = [0u8; 1];
+    i2c.write_read(address, &[BME680_REG_CHIP_ID], &mut chip_id).await.map_err(|_| ())?;
+    if chip_id[0] != BME680_CHIP_ID {
+        return Err(());
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------
+// Auto-detection
+// ---------------------------------------------------------------------
+
+/// Whichever sensor [`probe`] found attached, dispatched through one
+/// [`EnvSensor`] impl so callers never match on the part.
+pub enum DetectedSensor<I2C> {
+    Sht3x(Sht3x<I2C>),
+    Htu21d(Htu21d<I2C>),
+    Am2320(Am2320<I2C>),
+    Bme680(Bme680<I2C>),
+}
+
+impl<I2C: I2c> DetectedSensor<I2C> {
+    /// Enable or disable the on-chip heater, if the detected part has one -
+    /// only the SHT3x does, so every other variant reports unsupported. The
+    /// BME680's gas heater is a different concept (a profile fixed at
+    /// `init`, not a remotely-toggled on/off) and isn't driven by this.
+    pub async fn set_heater(&mut self, on: bool) -> Result<(), ()> {
+        match self {
+            DetectedSensor::Sht3x(s) => s.set_heater(on).await,
+            _ => Err(()),
+        }
+    }
+}
+
+impl<I2C: I2c> EnvSensor for DetectedSensor<I2C> {
+    async fn init(&mut self) -> Result<(), ()> {
+        match self {
+            DetectedSensor::Sht3x(s) => s.init().await,
+            DetectedSensor::Htu21d(s) => s.init().await,
+            DetectedSensor::Am2320(s) => s.init().await,
+            DetectedSensor::Bme680(s) => s.init().await,
+        }
+    }
+
+    async fn read(&mut self) -> Result<Measurement, ()> {
+        match self {
+            DetectedSensor::Sht3x(s) => s.read().await,
+            DetectedSensor::Htu21d(s) => s.read().await,
+            DetectedSensor::Am2320(s) => s.read().await,
+            DetectedSensor::Bme680(s) => s.read().await,
+        }
+    }
+}
+
+/// Try every supported sensor's address and identity/CRC check in turn,
+/// returning the first one that responds. Boards are populated with exactly
+/// one of these parts, so the first hit is taken as authoritative.
+pub async fn probe<I2C: I2c>(mut i2c: I2C) -> Result<DetectedSensor<I2C>, ()> {
+    for &address in &[0x44u8, 0x45u8] {
+        if probe_sht3x(&mut i2c, address).await.is_ok() {
+            defmt::info!("Detected SHT3x at I2C address 0x{:02X}", address);
+            return Ok(DetectedSensor::Sht3x(Sht3x { i2c, address }));
+        }
+    }
+    if probe_htu21d(&mut i2c).await.is_ok() {
+        defmt::info!("Detected HTU21D/Si7021 at I2C address 0x{:02X}", HTU21D_ADDRESS);
+        return Ok(DetectedSensor::Htu21d(Htu21d { i2c }));
+    }
+    if probe_am2320(&mut i2c).await.is_ok() {
+        defmt::info!("Detected AM2320 at I2C address 0x{:02X}", AM2320_ADDRESS);
+        return Ok(DetectedSensor::Am2320(Am2320 { i2c }));
+    }
+    for &address in &[0x76u8, 0x77u8] {
+        if probe_bme680(&mut i2c, address).await.is_ok() {
+            defmt::info!("Detected BME680 at I2C address 0x{:02X}", address);
+            return Ok(DetectedSensor::Bme680(Bme680::new(i2c, address, Bme680Config::default())));
+        }
+    }
+    defmt::warn!("No supported environmental sensor responded on I2C1");
+    Err(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc8_matches_sensirion_datasheet_example() {
+        // Sensirion's CRC-8 application note worked example: 0xBE 0xEF -> 0x92
+        assert_eq!(crc8(&[0xBE, 0xEF], 0xFF), 0x92);
+        assert_eq!(crc8(&[0x00, 0x00], 0xFF), 0x81);
+    }
+
+    fn sample(temp_raw: u16, hum_raw: u16) -> [u8; 6] {
+        let t = temp_raw.to_be_bytes();
+        let h = hum_raw.to_be_bytes();
+        [t[0], t[1], crc8(&t, 0xFF), h[0], h[1], crc8(&h, 0xFF)]
+    }
+
+    #[test]
+    fn sht3x_decode_converts_raw_counts_to_physical_units() {
+        let data = sample(0, 0);
+        let (temperature, humidity) = sht3x_decode(&data).expect("crc should validate");
+        assert!((temperature - (-45.0)).abs() < 0.001);
+        assert!((humidity - 0.0).abs() < 0.001);
+
+        let data = sample(0xFFFF, 0xFFFF);
+        let (temperature, humidity) = sht3x_decode(&data).expect("crc should validate");
+        assert!((temperature - 130.0).abs() < 0.01);
+        assert!((humidity - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn sht3x_decode_rejects_corrupted_temperature_byte() {
+        let mut data = sample(0x6400, 0x8000);
+        data[0] ^= 0xFF; // flip every bit of the temperature MSB without touching its CRC byte
+        assert!(sht3x_decode(&data).is_err());
+    }
+
+    #[test]
+    fn sht3x_decode_rejects_corrupted_humidity_byte() {
+        let mut data = sample(0x6400, 0x8000);
+        data[4] ^= 0xFF; // flip every bit of the humidity LSB without touching its CRC byte
+        assert!(sht3x_decode(&data).is_err());
+    }
+}